@@ -0,0 +1,236 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use crate::crypto::{field::FieldElement, sha256::sha256};
+
+/// `.bfc` compiled program layout, all integers little endian:
+///
+/// | bytes | field                                   |
+/// |-------|-----------------------------------------|
+/// | 4     | magic `\x7fBFC`                         |
+/// | 2     | format version                          |
+/// | 8     | instruction count `n`                   |
+/// | 32    | program digest, see `program_digest`    |
+/// | 8 * n | canonical `FieldElement` words          |
+pub const MAGIC: [u8; 4] = *b"\x7fBFC";
+pub const VERSION: u16 = 1;
+const HEADER_LEN: usize = 4 + 2 + 8 + 32;
+
+#[derive(Debug)]
+pub enum BytecodeError {
+    Io(std::io::Error),
+    /// File does not start with `MAGIC`.
+    BadMagic,
+    UnsupportedVersion(u16),
+    /// File ends before the header or the announced number of words.
+    Truncated {
+        expected: usize,
+        found: usize,
+    },
+    /// Bytes left over after the announced number of words.
+    TrailingData(usize),
+    /// Word is not reduced modulo the Goldilocks prime.
+    NonCanonicalWord {
+        index: usize,
+        value: u64,
+    },
+    /// Words do not hash to the digest stored in the header.
+    DigestMismatch,
+}
+
+impl Display for BytecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BytecodeError::Io(err) => write!(f, "io error: {}", err),
+            BytecodeError::BadMagic => write!(f, "not a .bfc file: bad magic bytes"),
+            BytecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported .bfc version {}", version)
+            }
+            BytecodeError::Truncated { expected, found } => {
+                write!(
+                    f,
+                    "truncated file: expected {} bytes, found {}",
+                    expected, found
+                )
+            }
+            BytecodeError::TrailingData(len) => write!(f, "{} unexpected trailing bytes", len),
+            BytecodeError::NonCanonicalWord { index, value } => {
+                write!(
+                    f,
+                    "word {} is not a canonical field element: {}",
+                    index, value
+                )
+            }
+            BytecodeError::DigestMismatch => write!(f, "program digest does not match"),
+        }
+    }
+}
+
+impl Error for BytecodeError {}
+
+impl From<std::io::Error> for BytecodeError {
+    fn from(err: std::io::Error) -> Self {
+        BytecodeError::Io(err)
+    }
+}
+
+/// SHA-256 over the little endian encoding of every instruction word.
+pub fn program_digest(code: &[FieldElement]) -> [u8; 32] {
+    let bytes: Vec<u8> = code.iter().flat_map(|word| word.0.to_le_bytes()).collect();
+    sha256(&bytes)
+}
+
+pub fn encode(code: &[FieldElement]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + code.len() * 8);
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(code.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&program_digest(code));
+    for word in code {
+        bytes.extend_from_slice(&word.0.to_le_bytes());
+    }
+    bytes
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<FieldElement>, BytecodeError> {
+    if bytes.len() < MAGIC.len() || bytes[..4] != MAGIC {
+        return Err(BytecodeError::BadMagic);
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(BytecodeError::Truncated {
+            expected: HEADER_LEN,
+            found: bytes.len(),
+        });
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != VERSION {
+        return Err(BytecodeError::UnsupportedVersion(version));
+    }
+    let count = u64::from_le_bytes(bytes[6..14].try_into().unwrap());
+    let digest = &bytes[14..HEADER_LEN];
+
+    let body = &bytes[HEADER_LEN..];
+    let expected = usize::try_from(count)
+        .ok()
+        .and_then(|count| count.checked_mul(8))
+        .unwrap_or(usize::MAX);
+    if body.len() < expected {
+        return Err(BytecodeError::Truncated {
+            expected: HEADER_LEN.saturating_add(expected),
+            found: bytes.len(),
+        });
+    }
+    if body.len() > expected {
+        return Err(BytecodeError::TrailingData(body.len() - expected));
+    }
+
+    let mut code = Vec::with_capacity(body.len() / 8);
+    for (index, chunk) in body.chunks(8).enumerate() {
+        let value = u64::from_le_bytes(chunk.try_into().unwrap());
+        if value >= FieldElement::modulus() {
+            return Err(BytecodeError::NonCanonicalWord { index, value });
+        }
+        code.push(FieldElement::from(value));
+    }
+    if program_digest(&code) != digest {
+        return Err(BytecodeError::DigestMismatch);
+    }
+    Ok(code)
+}
+
+pub fn write<W: Write>(writer: &mut W, code: &[FieldElement]) -> Result<(), BytecodeError> {
+    writer.write_all(&encode(code))?;
+    Ok(())
+}
+
+pub fn read<R: Read>(reader: &mut R) -> Result<Vec<FieldElement>, BytecodeError> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
+    decode(&bytes)
+}
+
+pub fn save<P: AsRef<Path>>(path: P, code: &[FieldElement]) -> Result<(), BytecodeError> {
+    fs::write(path, encode(code))?;
+    Ok(())
+}
+
+/// Loads a `.bfc` file into instructions ready for `Machine::new`.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<FieldElement>, BytecodeError> {
+    decode(&fs::read(path)?)
+}
+
+#[cfg(test)]
+fn sample_program() -> Vec<FieldElement> {
+    use crate::compiler::Compiler;
+
+    Compiler::new(include_str!("../examples/1.bf").to_string())
+        .compile()
+        .unwrap()
+}
+
+#[test]
+fn test_bytecode_round_trip() {
+    let code = sample_program();
+    let mut bytes = vec![];
+    write(&mut bytes, &code).unwrap();
+    assert_eq!(bytes.len(), HEADER_LEN + code.len() * 8);
+    assert_eq!(read(&mut bytes.as_slice()).unwrap(), code);
+}
+
+#[test]
+fn test_bytecode_rejects_bad_header() {
+    let mut bytes = encode(&sample_program());
+    bytes[0] = b'X';
+    assert!(matches!(decode(&bytes), Err(BytecodeError::BadMagic)));
+
+    let mut bytes = encode(&sample_program());
+    bytes[4] = 2;
+    assert!(matches!(
+        decode(&bytes),
+        Err(BytecodeError::UnsupportedVersion(2))
+    ));
+
+    let bytes = encode(&sample_program());
+    assert!(matches!(
+        decode(&bytes[..10]),
+        Err(BytecodeError::Truncated { .. })
+    ));
+}
+
+#[test]
+fn test_bytecode_rejects_truncated_or_trailing_body() {
+    let bytes = encode(&sample_program());
+    assert!(matches!(
+        decode(&bytes[..bytes.len() - 3]),
+        Err(BytecodeError::Truncated { .. })
+    ));
+
+    let mut bytes = encode(&sample_program());
+    bytes.push(0);
+    assert!(matches!(
+        decode(&bytes),
+        Err(BytecodeError::TrailingData(1))
+    ));
+}
+
+#[test]
+fn test_bytecode_rejects_corrupted_words() {
+    let mut bytes = encode(&sample_program());
+    bytes[HEADER_LEN] ^= 1;
+    assert!(matches!(decode(&bytes), Err(BytecodeError::DigestMismatch)));
+
+    let mut bytes = encode(&sample_program());
+    bytes[HEADER_LEN..HEADER_LEN + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(matches!(
+        decode(&bytes),
+        Err(BytecodeError::NonCanonicalWord {
+            index: 0,
+            value: u64::MAX
+        })
+    ));
+}
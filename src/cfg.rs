@@ -0,0 +1,346 @@
+//! Control-flow graph of a compiled program. Every `[` and `]` ends a basic block and
+//! branches on the current cell: to the loop body when it is nonzero and past the loop
+//! when it is zero.
+//!
+//! Procedures are separate subgraphs: `(` skips over the body, `:` continues after the
+//! call once the procedure returns, and the `)` block has no successors.
+
+use std::fmt::Write;
+
+use crate::{
+    compiler::{SourceMap, SourcePosition},
+    crypto::field::FieldElement,
+    instruction::{DecodeError, InstructionType},
+    registers::Registers,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    /// Address of the first instruction.
+    pub start: usize,
+    /// Address just past the last instruction, including a jump target word.
+    pub end: usize,
+    /// Commands of the block as source text, e.g. `>++[`.
+    pub commands: String,
+    /// Source positions of the first and last command, when a source map is given.
+    pub span: Option<(SourcePosition, SourcePosition)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Block(usize),
+    /// End of the program.
+    Exit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Last block running off the end of the program.
+    Fallthrough,
+    /// Taken when the current cell is nonzero.
+    NonZero,
+    /// Taken when the current cell is zero.
+    Zero,
+    /// `(` skipping over the procedure body it defines.
+    Define,
+    /// `:` continuing once the called procedure returns.
+    Call,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: Target,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+/// Decoded instructions with their addresses, and the addresses of the opening and
+/// closing bracket of every loop and procedure, indexed by the address of either.
+type Decoded = (Vec<(usize, InstructionType)>, Vec<Option<(usize, usize)>>);
+
+fn decode(code: &[FieldElement]) -> Result<Decoded, DecodeError> {
+    let mut instructions = vec![];
+    let mut loops = vec![None; code.len()];
+    let mut stack = vec![];
+    let mut ip = 0;
+    while ip < code.len() {
+        let ins_type = InstructionType::decode(code[ip])
+            .ok_or(DecodeError::InvalidOpcode { ip, word: code[ip] })?;
+        let opener = match ins_type {
+            InstructionType::JumpIfNotZero => Some(InstructionType::JumpIfZero),
+            InstructionType::ProcedureEnd => Some(InstructionType::ProcedureStart),
+            _ => None,
+        };
+        match ins_type {
+            InstructionType::JumpIfZero | InstructionType::ProcedureStart => {
+                stack.push((ip, ins_type))
+            }
+            _ if opener.is_some() => match stack.pop() {
+                Some((start, kind)) if Some(kind) == opener => {
+                    loops[start] = Some((start, ip));
+                    loops[ip] = Some((start, ip));
+                }
+                _ => return Err(DecodeError::UnmatchedBracket { ip }),
+            },
+            _ => (),
+        }
+        instructions.push((ip, ins_type));
+        ip += if ins_type.has_argument() { 2 } else { 1 };
+    }
+    match stack.pop() {
+        Some((ip, _)) => Err(DecodeError::UnmatchedBracket { ip }),
+        None => Ok((instructions, loops)),
+    }
+}
+
+impl Cfg {
+    /// Builds the graph of a compiled program. Blocks carry source spans when a source
+    /// map is given.
+    pub fn build(
+        code: &[FieldElement],
+        source_map: Option<&SourceMap>,
+    ) -> Result<Self, DecodeError> {
+        let (instructions, loops) = decode(code)?;
+
+        let mut blocks: Vec<BasicBlock> = vec![];
+        // Jump or procedure instruction ending each block and its address, if any.
+        let mut terminators = vec![];
+        let mut current: Option<BasicBlock> = None;
+        for (ip, ins_type) in &instructions {
+            let block = current.get_or_insert_with(|| BasicBlock {
+                start: *ip,
+                end: *ip,
+                commands: String::new(),
+                span: None,
+            });
+            block.end = ip + if ins_type.has_argument() { 2 } else { 1 };
+            block.commands.push_str(&ins_type.to_string());
+            if let Some(pos) = source_map.and_then(|map| map.get(*ip)) {
+                block.span = Some(block.span.map_or((pos, pos), |(first, _)| (first, pos)));
+            }
+            if ins_type.has_argument() || ins_type.is_extended() {
+                blocks.extend(current.take());
+                terminators.push(Some((*ip, *ins_type)));
+            }
+        }
+        if let Some(block) = current {
+            blocks.push(block);
+            terminators.push(None);
+        }
+
+        let target = |address: usize| match blocks.binary_search_by_key(&address, |b| b.start) {
+            Ok(index) => Target::Block(index),
+            Err(_) => Target::Exit,
+        };
+        let mut edges = vec![];
+        for (index, terminator) in terminators.iter().enumerate() {
+            let mut edge = |to, kind| {
+                edges.push(Edge {
+                    from: index,
+                    to,
+                    kind,
+                })
+            };
+            let Some((ip, ins_type)) = terminator else {
+                edge(target(blocks[index].end), EdgeKind::Fallthrough);
+                continue;
+            };
+            match ins_type {
+                // Both brackets lead into the body or past the loop.
+                InstructionType::JumpIfZero | InstructionType::JumpIfNotZero => {
+                    let (start, end) = loops[*ip].expect("brackets are matched");
+                    edge(target(start + 2), EdgeKind::NonZero);
+                    edge(target(end + 2), EdgeKind::Zero);
+                }
+                InstructionType::ProcedureStart => {
+                    let (_, end) = loops[*ip].expect("brackets are matched");
+                    edge(target(end + 1), EdgeKind::Define);
+                }
+                InstructionType::Call => edge(target(blocks[index].end), EdgeKind::Call),
+                // ')' returns to wherever the procedure was called from.
+                _ => (),
+            }
+        }
+        Ok(Cfg { blocks, edges })
+    }
+
+    /// How often each block was entered in a `Machine` trace.
+    pub fn execution_counts(&self, trace: &[Registers]) -> Vec<usize> {
+        let mut counts = vec![0; self.blocks.len()];
+        for row in trace {
+            let ip = row.ip.to_usize();
+            if let Ok(index) = self.blocks.binary_search_by_key(&ip, |block| block.start) {
+                counts[index] += 1;
+            }
+        }
+        counts
+    }
+
+    /// Graphviz DOT rendering. Each node shows the block's address range, source span and
+    /// commands, plus how often it ran when `counts` is given.
+    pub fn to_dot(&self, counts: Option<&[usize]>) -> String {
+        let node = |target: Target| match target {
+            Target::Block(index) => format!("b{}", index),
+            Target::Exit => "exit".to_string(),
+        };
+
+        let mut dot = String::from("digraph cfg {\n");
+        dot.push_str("  node [shape=box, fontname=\"monospace\"];\n");
+        dot.push_str("  entry [shape=point];\n");
+        dot.push_str("  exit [shape=doublecircle, label=\"exit\"];\n");
+        let first = if self.blocks.is_empty() {
+            Target::Exit
+        } else {
+            Target::Block(0)
+        };
+        writeln!(dot, "  entry -> {};", node(first)).unwrap();
+        for (index, block) in self.blocks.iter().enumerate() {
+            let mut label = format!("{}..{}", block.start, block.end);
+            if let Some((first, last)) = block.span {
+                write!(label, " @ {}-{}", first, last).unwrap();
+            }
+            write!(label, "\\n{}", block.commands).unwrap();
+            if let Some(counts) = counts {
+                write!(label, "\\nruns: {}", counts[index]).unwrap();
+            }
+            writeln!(dot, "  b{} [label=\"{}\"];", index, label).unwrap();
+        }
+        for edge in &self.edges {
+            let attributes = match edge.kind {
+                EdgeKind::Fallthrough => "",
+                EdgeKind::NonZero => " [label=\"nonzero\"]",
+                EdgeKind::Zero => " [label=\"zero\", style=dashed]",
+                EdgeKind::Define => " [label=\"define\", style=dotted]",
+                EdgeKind::Call => " [label=\"call\", style=dotted]",
+            };
+            writeln!(dot, "  b{} -> {}{};", edge.from, node(edge.to), attributes).unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+fn build_source(source: &str) -> Cfg {
+    let mut compiler = crate::compiler::Compiler::new(source.to_string());
+    let code = compiler.compile().unwrap();
+    Cfg::build(&code, Some(&compiler.source_map())).unwrap()
+}
+
+#[test]
+fn test_cfg_blocks_and_edges() {
+    let cfg = build_source("++[>+<-]>.");
+    let commands: Vec<&str> = cfg.blocks.iter().map(|b| b.commands.as_str()).collect();
+    assert_eq!(commands, vec!["++[", ">+<-]", ">."]);
+    assert_eq!(
+        cfg.edges,
+        vec![
+            Edge {
+                from: 0,
+                to: Target::Block(1),
+                kind: EdgeKind::NonZero
+            },
+            Edge {
+                from: 0,
+                to: Target::Block(2),
+                kind: EdgeKind::Zero
+            },
+            Edge {
+                from: 1,
+                to: Target::Block(1),
+                kind: EdgeKind::NonZero
+            },
+            Edge {
+                from: 1,
+                to: Target::Block(2),
+                kind: EdgeKind::Zero
+            },
+            Edge {
+                from: 2,
+                to: Target::Exit,
+                kind: EdgeKind::Fallthrough
+            },
+        ]
+    );
+    let at = |line, column| SourcePosition { line, column };
+    assert_eq!(cfg.blocks[1].span, Some((at(1, 4), at(1, 8))));
+}
+
+#[test]
+fn test_cfg_nested_loops() {
+    let cfg = build_source("+[>+[-]<-]");
+    let commands: Vec<&str> = cfg.blocks.iter().map(|b| b.commands.as_str()).collect();
+    assert_eq!(commands, vec!["+[", ">+[", "-]", "<-]"]);
+    let successors = |from| -> Vec<Target> {
+        cfg.edges
+            .iter()
+            .filter(|edge| edge.from == from)
+            .map(|edge| edge.to)
+            .collect()
+    };
+    assert_eq!(successors(0), vec![Target::Block(1), Target::Exit]);
+    assert_eq!(successors(1), vec![Target::Block(2), Target::Block(3)]);
+    assert_eq!(successors(2), vec![Target::Block(2), Target::Block(3)]);
+    assert_eq!(successors(3), vec![Target::Block(1), Target::Exit]);
+
+    let mut code = crate::compiler::Compiler::new("+[-]".to_string())
+        .compile()
+        .unwrap();
+    code.truncate(3);
+    assert_eq!(
+        Cfg::build(&code, None),
+        Err(DecodeError::UnmatchedBracket { ip: 1 })
+    );
+}
+
+#[test]
+fn test_cfg_execution_counts_and_dot() {
+    let source = "+++[>++[-]<-]>.";
+    let code = crate::compiler::Compiler::new(source.to_string())
+        .compile()
+        .unwrap();
+    let cfg = Cfg::build(&code, None).unwrap();
+    let run = crate::machine::run_code(code, b"");
+    run.result.unwrap();
+    let counts = cfg.execution_counts(&run.trace);
+    assert_eq!(counts, vec![1, 3, 6, 3, 1]);
+
+    let dot = cfg.to_dot(Some(&counts));
+    assert!(dot.starts_with("digraph cfg {\n"));
+    assert!(dot.contains("  b2 [label=\"10..13\\n-]\\nruns: 6\"];\n"));
+    assert!(dot.contains("  b4 -> exit;\n"));
+    assert!(dot.contains("  b0 -> b4 [label=\"zero\", style=dashed];\n"));
+}
+
+#[test]
+fn test_cfg_procedures() {
+    let config = crate::compiler::CompilerConfig {
+        extended: true,
+        ..Default::default()
+    };
+    let code = crate::compiler::Compiler::with_config("+(>+<):>.".to_string(), config)
+        .compile()
+        .unwrap();
+    let cfg = Cfg::build(&code, None).unwrap();
+    let commands: Vec<&str> = cfg.blocks.iter().map(|b| b.commands.as_str()).collect();
+    assert_eq!(commands, vec!["+(", ">+<)", ":", ">."]);
+    let edges: Vec<(usize, Target, EdgeKind)> = cfg
+        .edges
+        .iter()
+        .map(|edge| (edge.from, edge.to, edge.kind))
+        .collect();
+    assert_eq!(
+        edges,
+        vec![
+            (0, Target::Block(2), EdgeKind::Define),
+            (2, Target::Block(3), EdgeKind::Call),
+            (3, Target::Exit, EdgeKind::Fallthrough),
+        ]
+    );
+}
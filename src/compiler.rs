@@ -1,40 +1,707 @@
-use crate::crypto::field::FieldElement;
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use crate::{
+    bytecode, crypto::field::FieldElement, instruction::InstructionType, machine::strip_breakpoints,
+};
+
+/// Line and column of a symbol in the source, both starting at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourcePosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for SourcePosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Source position of every word the compiler emits, indexed by instruction address.
+/// Jump target words map to the bracket they belong to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    positions: Vec<SourcePosition>,
+}
+
+impl SourceMap {
+    pub fn get(&self, ip: usize) -> Option<SourcePosition> {
+        self.positions.get(ip).copied()
+    }
+
+    pub fn lookup(&self, ip: FieldElement) -> Option<SourcePosition> {
+        self.get(ip.to_usize())
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxError {
+    /// '[' that is never closed.
+    UnmatchedOpen(SourcePosition),
+    /// ']' without a preceding '['.
+    UnmatchedClose(SourcePosition),
+    /// Non-command character, only reported in strict mode.
+    UnexpectedCharacter(char, SourcePosition),
+    /// '(' that is never closed.
+    UnmatchedProcedureStart(SourcePosition),
+    /// ')' without a preceding '(', or closing a '(' before an inner '['.
+    UnmatchedProcedureEnd(SourcePosition),
+}
+
+impl SyntaxError {
+    pub fn position(&self) -> SourcePosition {
+        match self {
+            SyntaxError::UnmatchedOpen(pos)
+            | SyntaxError::UnmatchedClose(pos)
+            | SyntaxError::UnexpectedCharacter(_, pos)
+            | SyntaxError::UnmatchedProcedureStart(pos)
+            | SyntaxError::UnmatchedProcedureEnd(pos) => *pos,
+        }
+    }
+
+    /// Description of the error without its position.
+    pub fn message(&self) -> String {
+        match self {
+            SyntaxError::UnmatchedOpen(_) => "unmatched '['".to_string(),
+            SyntaxError::UnmatchedClose(_) => "unmatched ']'".to_string(),
+            SyntaxError::UnexpectedCharacter(symbol, _) => {
+                format!("unexpected character {:?}", symbol)
+            }
+            SyntaxError::UnmatchedProcedureStart(_) => "unmatched '('".to_string(),
+            SyntaxError::UnmatchedProcedureEnd(_) => "unmatched ')'".to_string(),
+        }
+    }
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.position(), self.message())
+    }
+}
+
+/// Every syntax error found in a program, in source order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub errors: Vec<SyntaxError>,
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, error) in self.errors.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for CompileError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct CompilerConfig {
+    /// Report non-command characters as errors instead of skipping them as comments.
+    pub strict: bool,
+    /// Keep `#` breakpoints. Without it they are comments, so proving builds never
+    /// contain them.
+    pub debug: bool,
+    /// Compile the pbrain-style procedure commands `(`, `)` and `:` instead of treating
+    /// them as comments.
+    pub extended: bool,
+}
+
+/// Non-whitespace characters of `code` with their positions, counting from `start`, and
+/// the position just past the end of `code`.
+fn symbols(code: &str, start: SourcePosition) -> (Vec<(char, SourcePosition)>, SourcePosition) {
+    let mut pos = start;
+    let mut symbols = vec![];
+    for symbol in code.chars() {
+        if symbol == '\n' {
+            pos.line += 1;
+            pos.column = 1;
+            continue;
+        }
+        if !symbol.is_whitespace() {
+            symbols.push((symbol, pos));
+        }
+        pos.column += 1;
+    }
+    (symbols, pos)
+}
+
+fn sort_errors(errors: &mut [SyntaxError]) {
+    errors.sort_by_key(|error| {
+        let pos = error.position();
+        (pos.line, pos.column)
+    });
+}
+
+/// Instructions emitted so far and the brackets still waiting for their match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Emitter {
+    instructions: Vec<FieldElement>,
+    source_map: SourceMap,
+    /// Argument word, position and kind of every open '[' and '('.
+    open_stack: Vec<(usize, SourcePosition, InstructionType)>,
+}
+
+impl Emitter {
+    /// Emits the instruction for `symbol`. Returns the address of an earlier argument
+    /// word that it filled in, if it closed a '[' or '('.
+    fn emit(
+        &mut self,
+        symbol: char,
+        pos: SourcePosition,
+        config: &CompilerConfig,
+        errors: &mut Vec<SyntaxError>,
+    ) -> Option<usize> {
+        let ins_type = match InstructionType::from_str(&symbol.to_string()) {
+            Ok(InstructionType::Breakpoint) if !config.debug => return None,
+            Ok(ins_type) if ins_type.is_extended() && !config.extended => return None,
+            Ok(ins_type) => ins_type,
+            Err(()) => {
+                if config.strict {
+                    errors.push(SyntaxError::UnexpectedCharacter(symbol, pos));
+                }
+                return None;
+            }
+        };
+        let opener = match ins_type {
+            InstructionType::JumpIfNotZero => Some(InstructionType::JumpIfZero),
+            InstructionType::ProcedureEnd => Some(InstructionType::ProcedureStart),
+            _ => None,
+        };
+        let mut start_pos = 0;
+        if let Some(opener) = opener {
+            match self.open_stack.last() {
+                Some((arg, _, kind)) if *kind == opener => {
+                    start_pos = *arg;
+                    self.open_stack.pop();
+                }
+                _ if opener == InstructionType::JumpIfZero => {
+                    errors.push(SyntaxError::UnmatchedClose(pos));
+                    return None;
+                }
+                _ => {
+                    errors.push(SyntaxError::UnmatchedProcedureEnd(pos));
+                    return None;
+                }
+            }
+        }
+        self.instructions.push(FieldElement::from(symbol as u64));
+        self.source_map.positions.push(pos);
+
+        match ins_type {
+            InstructionType::JumpIfZero | InstructionType::ProcedureStart => {
+                self.instructions.push(FieldElement::from(0));
+                self.source_map.positions.push(pos);
+                self.open_stack
+                    .push((self.instructions.len() - 1, pos, ins_type));
+                None
+            }
+            InstructionType::JumpIfNotZero => {
+                let loop_end_pos = self.instructions.len() + 1;
+                self.instructions[start_pos] = FieldElement::from(loop_end_pos - 1);
+                self.instructions.push(FieldElement::from(start_pos + 1));
+                self.source_map.positions.push(pos);
+                Some(start_pos)
+            }
+            // '(' skips the body by jumping onto its ')'.
+            InstructionType::ProcedureEnd => {
+                self.instructions[start_pos] = FieldElement::from(self.instructions.len() - 1);
+                Some(start_pos)
+            }
+            _ => None,
+        }
+    }
+
+    /// Undoes everything emitted from address `start` on, given the addresses those
+    /// instructions `patched`: words before `start` get their open jump target back and
+    /// their brackets are reopened in the order they were closed in.
+    fn rollback(&mut self, start: usize, patched: &[usize]) {
+        self.instructions.truncate(start);
+        self.source_map.positions.truncate(start);
+        self.open_stack.retain(|(arg, _, _)| *arg < start);
+        for &arg in patched.iter().rev().filter(|arg| **arg < start) {
+            self.instructions[arg] = FieldElement::from(0);
+            let kind = InstructionType::decode(self.instructions[arg - 1])
+                .expect("a patched word follows its bracket");
+            self.open_stack
+                .push((arg, self.source_map.positions[arg], kind));
+        }
+    }
+
+    /// Errors for the brackets that are still open.
+    fn unclosed(&self) -> impl Iterator<Item = SyntaxError> + '_ {
+        self.open_stack.iter().map(|(_, pos, kind)| match kind {
+            InstructionType::JumpIfZero => SyntaxError::UnmatchedOpen(*pos),
+            _ => SyntaxError::UnmatchedProcedureStart(*pos),
+        })
+    }
+}
 
 pub struct Compiler {
-    code: Vec<char>,
+    code: Vec<(char, SourcePosition)>,
+    config: CompilerConfig,
     instructions: Vec<FieldElement>,
+    source_map: SourceMap,
 }
 
 impl Compiler {
     pub fn new(code: String) -> Self {
-        let trimmed_code = code.chars().filter(|c| !c.is_whitespace()).collect();
+        Self::with_config(code, CompilerConfig::default())
+    }
+
+    pub fn with_config(code: String, config: CompilerConfig) -> Self {
+        Self {
+            code: symbols(&code, SourcePosition { line: 1, column: 1 }).0,
+            config,
+            instructions: vec![],
+            source_map: SourceMap::default(),
+        }
+    }
+
+    /// Compiler for commands that were already tokenized, e.g. by a `Dialect`. Positions
+    /// refer to wherever the tokens were read from.
+    pub fn from_tokens(
+        tokens: Vec<(InstructionType, SourcePosition)>,
+        config: CompilerConfig,
+    ) -> Self {
         Self {
-            code: trimmed_code,
+            code: tokens
+                .into_iter()
+                .map(|(ins_type, pos)| (ins_type.to_u8() as char, pos))
+                .collect(),
+            config,
             instructions: vec![],
+            source_map: SourceMap::default(),
         }
     }
 
-    pub fn compile(&mut self) -> Vec<FieldElement> {
-        let mut loop_stack = vec![];
-        for symbol in &self.code {
-            self.instructions.push(FieldElement::from(*symbol as u64));
+    pub fn compile(&mut self) -> Result<Vec<FieldElement>, CompileError> {
+        let mut emitter = Emitter::default();
+        let mut errors = vec![];
+        for (symbol, pos) in &self.code {
+            emitter.emit(*symbol, *pos, &self.config, &mut errors);
+        }
+        errors.extend(emitter.unclosed());
 
-            match *symbol {
-                '[' => {
-                    self.instructions.push(FieldElement::from(0));
-                    loop_stack.push(self.instructions.len() - 1);
-                }
-                ']' => {
-                    let start_pos = loop_stack.pop().unwrap();
-                    let loop_end_pos = self.instructions.len() as u8 + 1;
-                    self.instructions[start_pos] = FieldElement::from((loop_end_pos - 1) as u64);
-                    self.instructions
-                        .push(FieldElement::from((start_pos + 1) as u64));
-                }
-                _ => (),
+        if !errors.is_empty() {
+            sort_errors(&mut errors);
+            return Err(CompileError { errors });
+        }
+        self.instructions = emitter.instructions;
+        self.source_map = emitter.source_map;
+        Ok(self.instructions.clone())
+    }
+
+    /// Source map of the last successful `compile`.
+    pub fn source_map(&self) -> SourceMap {
+        self.source_map.clone()
+    }
+
+    /// Digest identifying the instructions of a successful `compile`, see
+    /// `bytecode::program_digest`. Until then it is the digest of the empty program.
+    /// Breakpoints are left out, as in `Machine::program_digest`.
+    pub fn program_digest(&self) -> [u8; 32] {
+        bytecode::program_digest(&strip_breakpoints(&self.instructions).code)
+    }
+}
+
+/// Earlier word that a fragment overwrote, i.e. the jump target of a bracket opened in
+/// a previous fragment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Patch {
+    pub address: usize,
+    pub value: FieldElement,
+}
+
+/// Result of compiling one fragment: `words` are appended at address `start`, then
+/// `patches` are applied to words from earlier fragments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledFragment {
+    pub start: usize,
+    pub words: Vec<FieldElement>,
+    pub patches: Vec<Patch>,
+}
+
+/// Compiles a program one fragment at a time, e.g. line by line in a REPL. Brackets may
+/// stay open across fragments; their jump targets are patched once they close.
+#[derive(Debug, Clone)]
+pub struct IncrementalCompiler {
+    config: CompilerConfig,
+    emitter: Emitter,
+    /// Position of the next fragment's first character.
+    next: SourcePosition,
+}
+
+impl IncrementalCompiler {
+    pub fn new(config: CompilerConfig) -> Self {
+        Self {
+            config,
+            emitter: Emitter::default(),
+            next: SourcePosition { line: 1, column: 1 },
+        }
+    }
+
+    /// Compiles `fragment` as the continuation of everything pushed so far. A fragment
+    /// with errors is rejected as a whole and leaves the compiler unchanged.
+    pub fn push(&mut self, fragment: &str) -> Result<CompiledFragment, CompileError> {
+        let emitter = &mut self.emitter;
+        let start = emitter.instructions.len();
+        let mut errors = vec![];
+        let mut patched = vec![];
+        let (symbols, next) = symbols(fragment, self.next);
+        for (symbol, pos) in symbols {
+            patched.extend(emitter.emit(symbol, pos, &self.config, &mut errors));
+        }
+        if !errors.is_empty() {
+            emitter.rollback(start, &patched);
+            sort_errors(&mut errors);
+            return Err(CompileError { errors });
+        }
+
+        self.next = next;
+        let instructions = &self.emitter.instructions;
+        Ok(CompiledFragment {
+            start,
+            words: instructions[start..].to_vec(),
+            patches: patched
+                .into_iter()
+                .filter(|address| *address < start)
+                .map(|address| Patch {
+                    address,
+                    value: instructions[address],
+                })
+                .collect(),
+        })
+    }
+
+    /// Every instruction so far. Jump targets of open brackets are still 0.
+    pub fn instructions(&self) -> &[FieldElement] {
+        &self.emitter.instructions
+    }
+
+    pub fn source_map(&self) -> SourceMap {
+        self.emitter.source_map.clone()
+    }
+
+    /// Number of `[` and `(` still waiting for their match.
+    pub fn open_brackets(&self) -> usize {
+        self.emitter.open_stack.len()
+    }
+
+    /// The whole program, or an error for every bracket that is still open.
+    pub fn finish(&self) -> Result<Vec<FieldElement>, CompileError> {
+        let errors: Vec<SyntaxError> = self.emitter.unclosed().collect();
+        if !errors.is_empty() {
+            return Err(CompileError { errors });
+        }
+        Ok(self.emitter.instructions.clone())
+    }
+}
+
+#[test]
+fn test_compile_balanced_loop() {
+    let mut compiler = Compiler::new("++>,<[>+.<-]".to_string());
+    let ins: Vec<u64> = compiler.compile().unwrap().iter().map(|x| x.0).collect();
+    assert_eq!(
+        ins,
+        vec![43, 43, 62, 44, 60, 91, 13, 62, 43, 46, 60, 45, 93, 7]
+    );
+}
+
+#[test]
+fn test_compile_unmatched_close() {
+    let mut compiler = Compiler::new("+]\n[-]]".to_string());
+    let err = compiler.compile().unwrap_err();
+    assert_eq!(
+        err.errors,
+        vec![
+            SyntaxError::UnmatchedClose(SourcePosition { line: 1, column: 2 }),
+            SyntaxError::UnmatchedClose(SourcePosition { line: 2, column: 4 }),
+        ]
+    );
+}
+
+#[test]
+fn test_compile_unmatched_open() {
+    let mut compiler = Compiler::new("[[\n  [-]".to_string());
+    let err = compiler.compile().unwrap_err();
+    assert_eq!(
+        err.errors,
+        vec![
+            SyntaxError::UnmatchedOpen(SourcePosition { line: 1, column: 1 }),
+            SyntaxError::UnmatchedOpen(SourcePosition { line: 1, column: 2 }),
+        ]
+    );
+    assert_eq!(err.to_string(), "1:1: unmatched '['\n1:2: unmatched '['");
+}
+
+#[test]
+fn test_compile_skips_comments() {
+    let code = "add two: ++ > , < [ > + . < - ] done".to_string();
+    let ins: Vec<u64> = Compiler::new(code)
+        .compile()
+        .unwrap()
+        .iter()
+        .map(|x| x.0)
+        .collect();
+    assert_eq!(
+        ins,
+        vec![43, 43, 62, 44, 60, 91, 13, 62, 43, 46, 60, 45, 93, 7]
+    );
+}
+
+#[test]
+fn test_compile_source_map() {
+    let mut compiler = Compiler::new("+ comment\n  [-]".to_string());
+    let ins = compiler.compile().unwrap();
+    let source_map = compiler.source_map();
+    assert_eq!(source_map.len(), ins.len());
+    let at = |line, column| Some(SourcePosition { line, column });
+    assert_eq!(source_map.get(0), at(1, 1));
+    assert_eq!(source_map.get(1), at(2, 3));
+    assert_eq!(source_map.get(2), at(2, 3));
+    assert_eq!(source_map.get(3), at(2, 4));
+    assert_eq!(source_map.lookup(FieldElement::from(4)), at(2, 5));
+    assert_eq!(source_map.get(6), None);
+
+    let mut failed = Compiler::new("+\n[-".to_string());
+    failed.compile().unwrap_err();
+    assert_eq!(failed.source_map().len(), 0);
+}
+
+#[test]
+fn test_compile_strict_rejects_comments() {
+    let config = CompilerConfig {
+        strict: true,
+        ..Default::default()
+    };
+    let mut compiler = Compiler::with_config("+a\n [x]".to_string(), config);
+    let err = compiler.compile().unwrap_err();
+    assert_eq!(
+        err.errors,
+        vec![
+            SyntaxError::UnexpectedCharacter('a', SourcePosition { line: 1, column: 2 }),
+            SyntaxError::UnexpectedCharacter('x', SourcePosition { line: 2, column: 3 }),
+        ]
+    );
+}
+
+/// Deterministic pseudo-random program with `len` symbols and nested loops.
+#[cfg(test)]
+fn generate_program(len: usize, seed: u64) -> String {
+    let mut state = seed;
+    let mut depth = 0;
+    let mut program = String::new();
+    for _ in 0..len {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let symbol = match (state >> 33) % 8 {
+            0 if depth > 0 => {
+                depth -= 1;
+                ']'
+            }
+            1 => {
+                depth += 1;
+                '['
+            }
+            2 => '>',
+            3 => '<',
+            4 | 5 => '+',
+            6 => '-',
+            _ => '.',
+        };
+        program.push(symbol);
+    }
+    program.push_str(&"]".repeat(depth));
+    program
+}
+
+/// Checks every '[' / ']' pair in `ins` against an independent bracket match.
+#[cfg(test)]
+fn assert_jump_targets(ins: &[FieldElement]) {
+    let mut stack = vec![];
+    let mut pairs = 0;
+    let mut ip = 0;
+    while ip < ins.len() {
+        match ins[ip].0 {
+            91 => {
+                stack.push(ip);
+                ip += 2;
+            }
+            93 => {
+                let open = stack.pop().unwrap();
+                // '[' jumps to the argument of ']', which the next cycle steps past.
+                assert_eq!(ins[open + 1], FieldElement::from(ip + 1));
+                // ']' jumps back to the first instruction of the loop body.
+                assert_eq!(ins[ip + 1], FieldElement::from(open + 2));
+                pairs += 1;
+                ip += 2;
             }
+            _ => ip += 1,
         }
+    }
+    assert!(stack.is_empty());
+    assert!(pairs > 0);
+}
+
+#[test]
+fn test_compile_jump_targets_past_u8_range() {
+    let code = format!("{}[{}]", "+".repeat(300), "-".repeat(300));
+    let ins = Compiler::new(code).compile().unwrap();
+    assert_eq!(ins[301], FieldElement::from(603));
+    assert_eq!(ins[603], FieldElement::from(302));
+    assert_jump_targets(&ins);
+}
+
+#[test]
+fn test_compile_large_generated_programs() {
+    for (len, seed) in [(1_000, 1), (5_000, 2), (20_000, 3), (70_000, 4)] {
+        let code = generate_program(len, seed);
+        let ins = Compiler::new(code).compile().unwrap();
+        assert!(ins.len() > len);
+        assert_jump_targets(&ins);
+    }
+}
+
+#[test]
+fn test_compile_procedures() {
+    let extended = CompilerConfig {
+        extended: true,
+        ..Default::default()
+    };
+    let compile = |code: &str, config: CompilerConfig| {
+        Compiler::with_config(code.to_string(), config).compile()
+    };
+    let ins: Vec<u64> = compile("+(-)::", extended.clone())
+        .unwrap()
+        .iter()
+        .map(|x| x.0)
+        .collect();
+    assert_eq!(ins, vec![43, 40, 4, 45, 41, 58, 58]);
+    // Without the extension they are comments.
+    let ins: Vec<u64> = compile("+(-)::", CompilerConfig::default())
+        .unwrap()
+        .iter()
+        .map(|x| x.0)
+        .collect();
+    assert_eq!(ins, vec![43, 45]);
+
+    let at = |line, column| SourcePosition { line, column };
+    assert_eq!(
+        compile("(]", extended.clone()).unwrap_err().errors,
+        vec![
+            SyntaxError::UnmatchedProcedureStart(at(1, 1)),
+            SyntaxError::UnmatchedClose(at(1, 2)),
+        ]
+    );
+    assert_eq!(
+        compile(")[", extended).unwrap_err().to_string(),
+        "1:1: unmatched ')'\n1:2: unmatched '['"
+    );
+}
+
+#[test]
+fn test_compile_program_digest() {
+    let mut compiler = Compiler::new("++[->+<]".to_string());
+    let ins = compiler.compile().unwrap();
+    assert_eq!(compiler.program_digest(), bytecode::program_digest(&ins));
+    let machine = crate::machine::Machine::new(ins, std::io::empty(), std::io::sink());
+    assert_eq!(machine.program_digest(), compiler.program_digest());
+    // Comments do not change the program, any instruction does.
+    let mut commented = Compiler::new("add: ++ [->+<]".to_string());
+    commented.compile().unwrap();
+    assert_eq!(commented.program_digest(), compiler.program_digest());
+    let mut other = Compiler::new("++[->-<]".to_string());
+    other.compile().unwrap();
+    assert_ne!(other.program_digest(), compiler.program_digest());
+    // Breakpoints in a debug build are not part of the program either.
+    let config = CompilerConfig {
+        debug: true,
+        ..Default::default()
+    };
+    let mut debug = Compiler::with_config("+#+".to_string(), config);
+    let ins = debug.compile().unwrap();
+    let machine = crate::machine::Machine::new(ins, std::io::empty(), std::io::sink());
+    assert_eq!(debug.program_digest(), machine.program_digest());
+    let mut release = Compiler::new("+#+".to_string());
+    release.compile().unwrap();
+    assert_eq!(debug.program_digest(), release.program_digest());
+    // A failed compile emits no program, so it has the digest of the empty one.
+    let mut failed = Compiler::new("++[->+<".to_string());
+    failed.compile().unwrap_err();
+    assert_eq!(failed.program_digest(), bytecode::program_digest(&[]));
+}
 
-        self.instructions.clone()
+#[test]
+fn test_incremental_compile_matches_compiler() {
+    let fragments = ["++>,<", "[>+", "\n.<", "-]", " ok\n[-]"];
+    let mut compiler = IncrementalCompiler::new(CompilerConfig::default());
+    let mut code = vec![];
+    for fragment in fragments {
+        let compiled = compiler.push(fragment).unwrap();
+        assert_eq!(compiled.start, code.len());
+        code.extend(compiled.words);
+        for patch in compiled.patches {
+            code[patch.address] = patch.value;
+        }
+        assert_eq!(code, compiler.instructions());
     }
+    assert_eq!(compiler.open_brackets(), 0);
+
+    let mut whole = Compiler::new(fragments.concat());
+    assert_eq!(compiler.finish().unwrap(), whole.compile().unwrap());
+    assert_eq!(compiler.source_map(), whole.source_map());
+}
+
+#[test]
+fn test_incremental_compile_patches_and_errors() {
+    let mut compiler = IncrementalCompiler::new(CompilerConfig::default());
+    let opened = compiler.push("+[").unwrap();
+    assert_eq!(opened.patches, vec![]);
+    assert_eq!(compiler.open_brackets(), 1);
+    assert_eq!(
+        compiler.finish().unwrap_err().errors,
+        vec![SyntaxError::UnmatchedOpen(SourcePosition {
+            line: 1,
+            column: 2
+        })]
+    );
+
+    // A rejected fragment leaves the compiler as it was.
+    let before = compiler.emitter.clone();
+    let err = compiler.push("-]]").unwrap_err();
+    assert_eq!(err.to_string(), "1:5: unmatched ']'");
+    assert_eq!(compiler.emitter, before);
+    let mut nested = compiler.clone();
+    nested.push("[(").unwrap();
+    let before = nested.emitter.clone();
+    nested.push("[)]]]]").unwrap_err();
+    assert_eq!(nested.emitter, before);
+
+    let closed = compiler.push("-]").unwrap();
+    assert_eq!(closed.start, 3);
+    assert_eq!(
+        closed.patches,
+        vec![Patch {
+            address: 2,
+            value: FieldElement::from(5)
+        }]
+    );
+    assert_eq!(
+        compiler.source_map().get(3),
+        Some(SourcePosition { line: 1, column: 3 })
+    );
 }
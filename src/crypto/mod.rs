@@ -1,2 +1,3 @@
 pub mod field;
 pub mod polynomial;
+pub mod sha256;
@@ -0,0 +1,303 @@
+//! Substitution dialects of Brainfuck, where every command is spelled as a token such
+//! as `Ook. Ook?`. `Dialect::compiler` tokenizes the source and hands the commands to
+//! the regular `Compiler`, so dialect programs run and trace exactly like plain ones.
+//!
+//! Tokens are matched as whole words, longest first: the characters around a token must
+//! not be letters, digits or `_`, so `Book.` in a comment is not `Ook.`. Whitespace
+//! inside a token matches any run of whitespace, including line breaks. Everything else
+//! is a comment.
+
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use crate::{
+    compiler::{Compiler, CompilerConfig, SourcePosition},
+    instruction::InstructionType,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DialectError {
+    /// Table line that is not a command followed by a token.
+    InvalidLine { line: usize, text: String },
+    /// Token that is already used for another command.
+    DuplicateToken { line: usize, token: String },
+}
+
+impl Display for DialectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DialectError::InvalidLine { line, text } => {
+                write!(
+                    f,
+                    "line {}: expected a command and a token, found {:?}",
+                    line, text
+                )
+            }
+            DialectError::DuplicateToken { line, token } => {
+                write!(f, "line {}: token {:?} is already defined", line, token)
+            }
+        }
+    }
+}
+
+impl Error for DialectError {}
+
+#[derive(Debug, Clone)]
+pub struct Dialect {
+    /// Words of each token, longest token first.
+    tokens: Vec<(Vec<String>, InstructionType)>,
+}
+
+/// Commands in the order `>`, `<`, `+`, `-`, `.`, `,`, `[`, `]`.
+const COMMANDS: [InstructionType; 8] = [
+    InstructionType::Right,
+    InstructionType::Left,
+    InstructionType::Plus,
+    InstructionType::Minus,
+    InstructionType::PutChar,
+    InstructionType::ReadChar,
+    InstructionType::JumpIfZero,
+    InstructionType::JumpIfNotZero,
+];
+
+impl Dialect {
+    /// Dialect from `(token, command)` pairs.
+    pub fn new<S: AsRef<str>>(
+        tokens: impl IntoIterator<Item = (S, InstructionType)>,
+    ) -> Result<Self, DialectError> {
+        let table: Vec<String> = tokens
+            .into_iter()
+            .map(|(token, ins_type)| format!("{} {}", ins_type, token.as_ref()))
+            .collect();
+        Self::from_table(&table.join("\n"))
+    }
+
+    /// Dialect from a token table with one `<command> <token>` pair per line, e.g.
+    /// `+ inc`. Blank lines are ignored and a command may have several tokens.
+    pub fn from_table(table: &str) -> Result<Self, DialectError> {
+        let mut tokens: Vec<(Vec<String>, InstructionType)> = vec![];
+        for (index, text) in table.lines().enumerate() {
+            let line = index + 1;
+            let mut words = text.split_whitespace();
+            let Some(command) = words.next() else {
+                continue;
+            };
+            let token: Vec<String> = words.map(str::to_string).collect();
+            let ins_type = match InstructionType::from_str(command) {
+                Ok(ins_type) if !token.is_empty() => ins_type,
+                _ => {
+                    return Err(DialectError::InvalidLine {
+                        line,
+                        text: text.to_string(),
+                    })
+                }
+            };
+            if tokens.iter().any(|(existing, _)| *existing == token) {
+                return Err(DialectError::DuplicateToken {
+                    line,
+                    token: token.join(" "),
+                });
+            }
+            tokens.push((token, ins_type));
+        }
+        tokens.sort_by_key(|(words, _)| std::cmp::Reverse(words.join(" ").len()));
+        Ok(Self { tokens })
+    }
+
+    /// Dialect where every command is a pair of `word` with one of `.`, `?` and `!`.
+    fn pairs(word: &str) -> Self {
+        let pairs = [".?", "?.", "..", "!!", "!.", ".!", "!?", "?!"];
+        let tokens = pairs.iter().zip(COMMANDS).map(|(pair, ins_type)| {
+            let mut marks = pair.chars();
+            let first = marks.next().unwrap();
+            let second = marks.next().unwrap();
+            (format!("{word}{first} {word}{second}"), ins_type)
+        });
+        Self::new(tokens).expect("built-in dialects are valid")
+    }
+
+    /// Ook!, e.g. `Ook. Ook?` for `>`.
+    pub fn ook() -> Self {
+        Self::pairs("Ook")
+    }
+
+    /// Blub, Ook! spelled with `Blub`.
+    pub fn blub() -> Self {
+        Self::pairs("Blub")
+    }
+
+    /// Length of the token `words` when it starts at `chars[start]`.
+    fn match_at(chars: &[(char, SourcePosition)], start: usize, words: &[String]) -> Option<usize> {
+        let mut index = start;
+        for (word_index, word) in words.iter().enumerate() {
+            if word_index > 0 {
+                let whitespace = chars[index..]
+                    .iter()
+                    .take_while(|(symbol, _)| symbol.is_whitespace())
+                    .count();
+                if whitespace == 0 {
+                    return None;
+                }
+                index += whitespace;
+            }
+            for expected in word.chars() {
+                match chars.get(index) {
+                    Some((symbol, _)) if *symbol == expected => index += 1,
+                    _ => return None,
+                }
+            }
+        }
+        Some(index - start)
+    }
+
+    /// Whether `symbol` continues a word. A token that starts or ends with such a
+    /// character cannot start or end next to another one, so `Book.` holds no `Ook.`,
+    /// while punctuation ends a token by itself, as in `Ook. Ook?Ook. Ook?`.
+    fn is_word_char(symbol: char) -> bool {
+        symbol.is_alphanumeric() || symbol == '_'
+    }
+
+    /// Commands of `source` with the position of the first character of their token.
+    pub fn tokenize(&self, source: &str) -> Vec<(InstructionType, SourcePosition)> {
+        let mut chars = vec![];
+        for (line, text) in source.lines().enumerate() {
+            for (column, symbol) in text.chars().chain(std::iter::once('\n')).enumerate() {
+                let pos = SourcePosition {
+                    line: line + 1,
+                    column: column + 1,
+                };
+                chars.push((symbol, pos));
+            }
+        }
+
+        let mut commands = vec![];
+        let mut index = 0;
+        while index < chars.len() {
+            let after_word = index > 0 && Self::is_word_char(chars[index - 1].0);
+            let found = self.tokens.iter().find_map(|(words, ins_type)| {
+                let length = Self::match_at(&chars, index, words)?;
+                let first = chars[index].0;
+                let last = chars[index + length - 1].0;
+                let before_word = chars
+                    .get(index + length)
+                    .is_some_and(|(symbol, _)| Self::is_word_char(*symbol));
+                let joined = (after_word && Self::is_word_char(first))
+                    || (before_word && Self::is_word_char(last));
+                (!joined).then_some((length, *ins_type))
+            });
+            match found {
+                Some((length, ins_type)) => {
+                    commands.push((ins_type, chars[index].1));
+                    index += length;
+                }
+                None => index += 1,
+            }
+        }
+        commands
+    }
+
+    /// Plain Brainfuck for `source`, without its comments.
+    pub fn translate(&self, source: &str) -> String {
+        self.tokenize(source)
+            .into_iter()
+            .map(|(ins_type, _)| ins_type.to_string())
+            .collect()
+    }
+
+    /// Compiler for a program in this dialect. Errors and the source map refer to
+    /// positions in `source`.
+    pub fn compiler(&self, source: &str, config: CompilerConfig) -> Compiler {
+        Compiler::from_tokens(self.tokenize(source), config)
+    }
+}
+
+/// `source` written in `dialect`, one token per command.
+#[cfg(test)]
+fn encode(dialect: &Dialect, source: &str) -> String {
+    source
+        .chars()
+        .filter_map(|symbol| {
+            let ins_type = InstructionType::from_str(&symbol.to_string()).ok()?;
+            let (words, _) = dialect.tokens.iter().find(|(_, t)| *t == ins_type)?;
+            Some(words.join(" "))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[test]
+fn test_dialect_ook_and_blub_compile_like_brainfuck() {
+    let source = include_str!("../examples/1.bf");
+    let expected = Compiler::new(source.to_string()).compile().unwrap();
+    for dialect in [Dialect::ook(), Dialect::blub()] {
+        let program = encode(&dialect, source);
+        let mut compiler = dialect.compiler(&program, CompilerConfig::default());
+        assert_eq!(compiler.compile().unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_dialect_tokenize() {
+    let program = "Ook. Ook? hello\nOok! Ook!  Ook.\n Ook. Ook";
+    assert_eq!(Dialect::ook().translate(program), ">-+");
+    let at = |line, column| SourcePosition { line, column };
+    let positions: Vec<SourcePosition> = Dialect::ook()
+        .tokenize(program)
+        .into_iter()
+        .map(|(_, pos)| pos)
+        .collect();
+    assert_eq!(positions, vec![at(1, 1), at(2, 1), at(2, 12)]);
+}
+
+#[test]
+fn test_dialect_ignores_tokens_inside_comment_words() {
+    let program = "Book. Look? the cook said Ook. Ook? and\n_Ook! Ook! Ook! Ook!, then Ook.Ook.";
+    assert_eq!(Dialect::ook().translate(program), ">-");
+    // Tokens ending in punctuation need no gap before the next one.
+    assert_eq!(Dialect::ook().translate("Ook. Ook?Ook. Ook?"), ">>");
+    assert_eq!(Dialect::ook().translate("Ook! Ook!_ Ook! Ook!"), "--");
+    assert_eq!(
+        Dialect::blub().translate("Blubber. Blub. Blub? xBlub! Blub!"),
+        ">"
+    );
+
+    // A commented program runs like its plain Brainfuck.
+    let commented = format!(
+        "Cook. Hook? {}\nThe end. Ook? Book!",
+        encode(&Dialect::ook(), "++[>+<-]>.")
+    );
+    let expected = Compiler::new("++[>+<-]>.".to_string()).compile().unwrap();
+    let mut compiler = Dialect::ook().compiler(&commented, CompilerConfig::default());
+    assert_eq!(compiler.compile().unwrap(), expected);
+
+    let dialect = Dialect::from_table("+ inc\n. out").unwrap();
+    assert_eq!(dialect.translate("princess inc outside out."), "+.");
+}
+
+#[test]
+fn test_dialect_from_table() {
+    let dialect =
+        Dialect::from_table("+ inc\n- dec\n\n+ increment\n. out\n[ while\n] end").unwrap();
+    assert_eq!(
+        dialect.translate("inc increment while dec end out"),
+        "++[-]."
+    );
+
+    let mut compiler = dialect.compiler("while inc", CompilerConfig::default());
+    let err = compiler.compile().unwrap_err();
+    assert_eq!(err.to_string(), "1:1: unmatched '['");
+
+    assert_eq!(
+        Dialect::from_table("+ inc\n- inc").unwrap_err(),
+        DialectError::DuplicateToken {
+            line: 2,
+            token: "inc".to_string()
+        }
+    );
+    assert_eq!(
+        Dialect::from_table("x inc").unwrap_err(),
+        DialectError::InvalidLine {
+            line: 1,
+            text: "x inc".to_string()
+        }
+    );
+}
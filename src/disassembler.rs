@@ -0,0 +1,196 @@
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use crate::{crypto::field::FieldElement, instruction::InstructionType};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisassembleError {
+    /// Word at `ip` is not a known opcode.
+    InvalidOpcode { ip: usize, word: FieldElement },
+    /// Jump at `ip` is the last word, so it has no target.
+    MissingArgument { ip: usize },
+    /// Line of a listing that is not `<address>: <mnemonic> [-> <target>]`.
+    InvalidLine { line: usize, text: String },
+    /// Address on a listing line does not match where its instruction lands.
+    AddressMismatch {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl Display for DisassembleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DisassembleError::InvalidOpcode { ip, word } => {
+                write!(f, "invalid opcode {} at address {}", word, ip)
+            }
+            DisassembleError::MissingArgument { ip } => {
+                write!(f, "jump at address {} has no target", ip)
+            }
+            DisassembleError::InvalidLine { line, text } => {
+                write!(f, "line {}: cannot parse {:?}", line, text)
+            }
+            DisassembleError::AddressMismatch {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected address {}, found {}",
+                line, expected, found
+            ),
+        }
+    }
+}
+
+impl Error for DisassembleError {}
+
+/// Renders compiled instructions as one `<address>: <mnemonic>` line per instruction.
+/// Jumps show the address execution continues at when the jump is taken, e.g. `5: [ -> 14`.
+pub fn disassemble(code: &[FieldElement]) -> Result<String, DisassembleError> {
+    let mut listing = String::new();
+    let mut ip = 0;
+    while ip < code.len() {
+        let ins_type = InstructionType::decode(code[ip])
+            .ok_or(DisassembleError::InvalidOpcode { ip, word: code[ip] })?;
+        match ins_type {
+            InstructionType::JumpIfZero
+            | InstructionType::JumpIfNotZero
+            | InstructionType::ProcedureStart => {
+                let argument = *code
+                    .get(ip + 1)
+                    .ok_or(DisassembleError::MissingArgument { ip })?;
+                // '[' lands on the argument of ']' and '(' on its ')', both step past it
+                // on the next cycle. ']' lands right before the loop body.
+                let target = match ins_type {
+                    InstructionType::JumpIfNotZero => argument,
+                    _ => argument + FieldElement::one(),
+                };
+                listing.push_str(&format!("{}: {} -> {}\n", ip, ins_type, target));
+                ip += 2;
+            }
+            _ => {
+                listing.push_str(&format!("{}: {}\n", ip, ins_type));
+                ip += 1;
+            }
+        }
+    }
+    Ok(listing)
+}
+
+/// Parses a listing produced by `disassemble` back into instructions.
+pub fn assemble(listing: &str) -> Result<Vec<FieldElement>, DisassembleError> {
+    let mut code = vec![];
+    for (index, text) in listing.lines().enumerate() {
+        let line = index + 1;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let invalid_line = || DisassembleError::InvalidLine {
+            line,
+            text: text.to_string(),
+        };
+        let (address, rest) = text.split_once(':').ok_or_else(invalid_line)?;
+        let address: usize = address.trim().parse().map_err(|_| invalid_line())?;
+        if address != code.len() {
+            return Err(DisassembleError::AddressMismatch {
+                line,
+                expected: code.len(),
+                found: address,
+            });
+        }
+        let (mnemonic, target) = match rest.split_once("->") {
+            Some((mnemonic, target)) => {
+                let target: u64 = target.trim().parse().map_err(|_| invalid_line())?;
+                (mnemonic.trim(), Some(FieldElement::from(target)))
+            }
+            None => (rest.trim(), None),
+        };
+        let ins_type = InstructionType::from_str(mnemonic).map_err(|_| invalid_line())?;
+        code.push(FieldElement::from(ins_type.to_u8() as u64));
+        match (ins_type, target) {
+            (InstructionType::JumpIfZero | InstructionType::ProcedureStart, Some(target)) => {
+                code.push(target - FieldElement::one());
+            }
+            (InstructionType::JumpIfNotZero, Some(target)) => code.push(target),
+            (ins_type, None) if !ins_type.has_argument() => (),
+            _ => return Err(invalid_line()),
+        }
+    }
+    Ok(code)
+}
+
+#[test]
+fn test_disassemble() {
+    use crate::compiler::Compiler;
+
+    let code = Compiler::new("++>,<[>+.<-]".to_string()).compile().unwrap();
+    let listing = disassemble(&code).unwrap();
+    let expected =
+        "0: +\n1: +\n2: >\n3: ,\n4: <\n5: [ -> 14\n7: >\n8: +\n9: .\n10: <\n11: -\n12: ] -> 7\n";
+    assert_eq!(listing, expected);
+}
+
+#[test]
+fn test_disassemble_invalid_opcode() {
+    let code = vec![FieldElement::from(43u64), FieldElement::from(300u64)];
+    assert_eq!(
+        disassemble(&code),
+        Err(DisassembleError::InvalidOpcode {
+            ip: 1,
+            word: FieldElement::from(300u64)
+        })
+    );
+    let code = vec![FieldElement::from(91u64)];
+    assert_eq!(
+        disassemble(&code),
+        Err(DisassembleError::MissingArgument { ip: 0 })
+    );
+}
+
+#[test]
+fn test_disassemble_round_trip() {
+    use crate::compiler::Compiler;
+
+    let programs = [
+        "++>,<[>+.<-]".to_string(),
+        include_str!("../examples/1.bf").to_string(),
+        format!("+[{}[-]>[<+>-]]", ">+".repeat(200)),
+    ];
+    for program in programs {
+        let code = Compiler::new(program).compile().unwrap();
+        let listing = disassemble(&code).unwrap();
+        assert_eq!(assemble(&listing).unwrap(), code);
+    }
+
+    let config = crate::compiler::CompilerConfig {
+        extended: true,
+        ..Default::default()
+    };
+    let code = Compiler::with_config("+(>[-]<)::".to_string(), config)
+        .compile()
+        .unwrap();
+    let listing = disassemble(&code).unwrap();
+    assert!(listing.starts_with("0: +\n1: ( -> 11\n"));
+    assert_eq!(assemble(&listing).unwrap(), code);
+}
+
+#[test]
+fn test_assemble_rejects_bad_listing() {
+    assert_eq!(
+        assemble("0: +\n2: -"),
+        Err(DisassembleError::AddressMismatch {
+            line: 2,
+            expected: 1,
+            found: 2
+        })
+    );
+    assert!(matches!(
+        assemble("0: ["),
+        Err(DisassembleError::InvalidLine { line: 1, .. })
+    ));
+    assert!(matches!(
+        assemble("0: x"),
+        Err(DisassembleError::InvalidLine { line: 1, .. })
+    ));
+}
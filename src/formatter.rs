@@ -0,0 +1,178 @@
+//! Canonical layout for plain Brainfuck source:
+//!
+//! - `[` and `]` sit on their own line and loop bodies are indented one level deeper.
+//! - Straight-line code stays on one line, with runs of the same command separated by
+//!   a space, e.g. `++++ > ++ <`.
+//! - Comments keep their text and line breaks, each on its own line at the current
+//!   indentation.
+//!
+//! Only plain `.bf` files are supported, see `is_formattable`. Preprocessor directives
+//! and repeat counts in `.bfm` files contain command characters and would be
+//! rearranged, and dialect tokens such as `Ook.` would be split apart.
+
+use std::str::FromStr;
+
+use crate::{
+    compiler::{CompileError, Compiler},
+    instruction::InstructionType,
+};
+
+const INDENT: &str = "  ";
+
+struct Formatter {
+    output: String,
+    line: String,
+    depth: usize,
+}
+
+impl Formatter {
+    fn push_line(&mut self, text: &str) {
+        self.output.push_str(&INDENT.repeat(self.depth));
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    fn flush(&mut self) {
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.push_line(&line);
+        }
+    }
+
+    fn command(&mut self, symbol: char) {
+        match symbol {
+            '[' => {
+                self.flush();
+                self.push_line("[");
+                self.depth += 1;
+            }
+            ']' => {
+                self.flush();
+                self.depth -= 1;
+                self.push_line("]");
+            }
+            _ => {
+                if !self.line.is_empty() && !self.line.ends_with(symbol) {
+                    self.line.push(' ');
+                }
+                self.line.push(symbol);
+            }
+        }
+    }
+
+    /// Text between two commands. Whitespace only separates, other text is a comment.
+    fn comment(&mut self, text: &str) {
+        for piece in text.lines() {
+            let piece = piece.split_whitespace().collect::<Vec<_>>().join(" ");
+            if !piece.is_empty() {
+                self.flush();
+                self.push_line(&piece);
+            }
+        }
+    }
+}
+
+/// Plain commands and `#`. Procedure commands stay comments, like in the default compiler.
+fn is_command(symbol: char) -> bool {
+    InstructionType::from_str(&symbol.to_string()).is_ok_and(|ins_type| !ins_type.is_extended())
+}
+
+/// Rewrites `source` in canonical layout. Fails on the same bracket errors as `Compiler`.
+pub fn format(source: &str) -> Result<String, CompileError> {
+    Compiler::new(source.to_string()).compile()?;
+
+    let mut formatter = Formatter {
+        output: String::new(),
+        line: String::new(),
+        depth: 0,
+    };
+    let mut comment = String::new();
+    for symbol in source.chars() {
+        if is_command(symbol) {
+            formatter.comment(&std::mem::take(&mut comment));
+            formatter.command(symbol);
+        } else {
+            comment.push(symbol);
+        }
+    }
+    formatter.comment(&comment);
+    formatter.flush();
+    Ok(formatter.output)
+}
+
+/// Whether the file at `path` is plain Brainfuck that `format` can rewrite safely.
+pub fn is_formattable(path: &str) -> bool {
+    path.ends_with(".bf")
+}
+
+/// Whether `source` is already in canonical layout.
+pub fn check(source: &str) -> Result<bool, CompileError> {
+    Ok(format(source)? == source)
+}
+
+#[test]
+fn test_format_layout() {
+    let source = "++++[>++<-]>.";
+    assert_eq!(format(source).unwrap(), "++++\n[\n  > ++ < -\n]\n> .\n");
+}
+
+#[test]
+fn test_format_nested_loops_and_comments() {
+    let source = "set up +++ [ outer\n>+++[ inner >+<-\n]  <- ] done\nbye";
+    let expected = "\
+set up
++++
+[
+  outer
+  > +++
+  [
+    inner
+    > + < -
+  ]
+  < -
+]
+done
+bye
+";
+    assert_eq!(format(source).unwrap(), expected);
+}
+
+#[test]
+fn test_format_is_idempotent() {
+    use crate::crypto::field::FieldElement;
+
+    let compile = |source: &str| -> Vec<FieldElement> {
+        Compiler::new(source.to_string()).compile().unwrap()
+    };
+    let sources = [
+        include_str!("../examples/1.bf"),
+        "a +[-> b ++<]\n\nc\n d ,.",
+        "",
+    ];
+    for source in sources {
+        let formatted = format(source).unwrap();
+        assert!(check(&formatted).unwrap());
+        assert_eq!(compile(&formatted), compile(source));
+    }
+}
+
+#[test]
+fn test_format_check() {
+    assert!(check("+\n[\n  -\n]\n").unwrap());
+    assert!(!check("+[-]").unwrap());
+    assert!(check("+[").is_err());
+}
+
+#[test]
+fn test_format_only_plain_sources() {
+    assert!(is_formattable("examples/1.bf"));
+    for path in [
+        "examples/1.ook",
+        "macros.bfm",
+        "program.blub",
+        "program.bfc",
+        "bf",
+    ] {
+        assert!(!is_formattable(path), "{}", path);
+    }
+}
@@ -0,0 +1,147 @@
+//! Generates Brainfuck programs that print a given byte string. Cell 0 is a loop
+//! counter and cell 1 holds the byte being printed, so large steps between bytes are
+//! written as multiplication loops such as `<++++++++[>+++++++++<-]>`.
+
+use std::{error::Error, fmt::Display};
+
+use crate::{compiler::Compiler, machine::Machine};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeneratorError {
+    /// Requested trace is shorter than the shortest program for the text.
+    TraceTooShort { minimum: usize },
+}
+
+impl Display for GeneratorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeneratorError::TraceTooShort { minimum } => {
+                write!(f, "trace length must be at least {}", minimum)
+            }
+        }
+    }
+}
+
+impl Error for GeneratorError {}
+
+/// Shortest code that adds `delta` to the current cell, using cell 0 as loop counter.
+fn step(delta: i32) -> String {
+    let (up, down) = if delta >= 0 { ('+', '-') } else { ('-', '+') };
+    let distance = delta.unsigned_abs() as usize;
+    let mut best = up.to_string().repeat(distance);
+    for factor in 2..=distance {
+        let times = distance / factor;
+        // Undershoot and top up, or overshoot and step back.
+        for (times, rest, rest_symbol) in [
+            (times, distance - factor * times, up),
+            (times + 1, factor * (times + 1) - distance, down),
+        ] {
+            if times < 2 {
+                continue;
+            }
+            let candidate = format!(
+                "<{}[>{}<-]>{}",
+                "+".repeat(factor),
+                up.to_string().repeat(times),
+                rest_symbol.to_string().repeat(rest)
+            );
+            if candidate.len() < best.len() {
+                best = candidate;
+            }
+        }
+    }
+    best
+}
+
+/// Program that prints exactly `text`. An overshooting step may take cell 1 below 0 for
+/// a moment, but every printed byte is the same in every `CellMode`.
+pub fn generate(text: &[u8]) -> String {
+    let mut program = String::from(">");
+    let mut current = 0i32;
+    for byte in text {
+        program.push_str(&step(*byte as i32 - current));
+        program.push('.');
+        current = *byte as i32;
+    }
+    program
+}
+
+/// Number of rows `Machine` records when running `program`.
+fn trace_length(program: &str) -> usize {
+    let code = Compiler::new(program.to_string())
+        .compile()
+        .expect("generated programs are balanced");
+    let mut machine = Machine::new(code, std::io::empty(), std::io::sink());
+    machine
+        .execute()
+        .expect("generated programs do not read input");
+    machine.get_trace().len()
+}
+
+/// Program that prints `text` and whose execution trace has exactly `trace_length`
+/// rows, padded with pointer moves that do not change the output.
+pub fn generate_with_trace_length(
+    text: &[u8],
+    trace_length: usize,
+) -> Result<String, GeneratorError> {
+    let mut program = generate(text);
+    let minimum = self::trace_length(&program);
+    if trace_length < minimum {
+        return Err(GeneratorError::TraceTooShort { minimum });
+    }
+    let padding = trace_length - minimum;
+    program.push_str(&"<>".repeat(padding / 2));
+    program.push_str(&">".repeat(padding % 2));
+    Ok(program)
+}
+
+#[cfg(test)]
+fn run(program: &str) -> Vec<u8> {
+    let code = Compiler::new(program.to_string()).compile().unwrap();
+    let program = crate::ir::lower(&code).unwrap();
+    let mut output = vec![];
+    crate::ir::execute(&program, &mut std::io::empty(), &mut output).unwrap();
+    output
+}
+
+#[test]
+fn test_generate_prints_text() {
+    for text in [
+        &b"Hello World!\n"[..],
+        b"",
+        b"a",
+        b"zzz\x00\xff\x01",
+        b"The quick brown fox",
+    ] {
+        assert_eq!(run(&generate(text)), text);
+    }
+}
+
+#[test]
+fn test_generate_uses_multiplication_loops() {
+    let program = generate(b"A");
+    assert!(program.contains('['));
+    assert!(program.len() < 65);
+    // Far shorter than stepping between the bytes one `+` or `-` at a time.
+    let text = b"Hello World!\n";
+    let naive: usize = std::iter::once(&0u8)
+        .chain(text)
+        .zip(text)
+        .map(|(from, to)| from.abs_diff(*to) as usize + 1)
+        .sum();
+    assert!(generate(text).len() * 2 < naive);
+}
+
+#[test]
+fn test_generate_with_trace_length() {
+    let minimum = trace_length(&generate(b"Hi"));
+    for target in [minimum, minimum + 1, minimum + 2, minimum + 1001] {
+        let program = generate_with_trace_length(b"Hi", target).unwrap();
+        assert_eq!(trace_length(&program), target);
+        assert_eq!(run(&program), b"Hi");
+    }
+    assert_eq!(
+        generate_with_trace_length(b"Hi", minimum - 1),
+        Err(GeneratorError::TraceTooShort { minimum })
+    );
+}
@@ -1,12 +1,39 @@
-use std::{fmt::Display, str::FromStr};
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use crate::crypto::field::FieldElement;
+
+/// Compiled program whose instructions or brackets cannot be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Word at `ip` is not a known opcode.
+    InvalidOpcode { ip: usize, word: FieldElement },
+    /// Bracket at `ip` has no matching bracket of the same kind.
+    UnmatchedBracket { ip: usize },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::InvalidOpcode { ip, word } => {
+                write!(f, "invalid opcode {} at address {}", word, ip)
+            }
+            DecodeError::UnmatchedBracket { ip } => {
+                write!(f, "unmatched bracket at address {}", ip)
+            }
+        }
+    }
+}
+
+impl Error for DecodeError {}
 
 #[derive(Debug, Clone)]
 pub struct Instruction {
     pub ins_type: InstructionType,
+    /// Number of times the instruction repeats, see `ir::lower`.
     pub argument: u8,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum InstructionType {
     // '>': Increment the data pointer (to point to the next cell to the right).
     Right,
@@ -24,6 +51,14 @@ pub enum InstructionType {
     JumpIfZero,
     // ']': If the byte at the data pointer is nonzero, then instead of moving the instruction pointer forward to the next command, jump it back to the command after the matching '[' command.
     JumpIfNotZero,
+    // '#': Debug breakpoint, only compiled with `CompilerConfig::debug`. `Machine` dumps its state without recording a trace row.
+    Breakpoint,
+    // '(': Extended set only. Defines the procedure numbered by the current cell as the code up to the matching ')' and jumps past it.
+    ProcedureStart,
+    // ')': Extended set only. Returns to the instruction after the ':' that called the procedure.
+    ProcedureEnd,
+    // ':': Extended set only. Calls the procedure numbered by the current cell.
+    Call,
 }
 
 impl FromStr for InstructionType {
@@ -39,6 +74,10 @@ impl FromStr for InstructionType {
             "," => Ok(InstructionType::ReadChar),
             "[" => Ok(InstructionType::JumpIfZero),
             "]" => Ok(InstructionType::JumpIfNotZero),
+            "#" => Ok(InstructionType::Breakpoint),
+            "(" => Ok(InstructionType::ProcedureStart),
+            ")" => Ok(InstructionType::ProcedureEnd),
+            ":" => Ok(InstructionType::Call),
             _ => Err(()),
         }
     }
@@ -55,6 +94,10 @@ impl Display for InstructionType {
             InstructionType::ReadChar => ",",
             InstructionType::JumpIfZero => "[",
             InstructionType::JumpIfNotZero => "]",
+            InstructionType::Breakpoint => "#",
+            InstructionType::ProcedureStart => "(",
+            InstructionType::ProcedureEnd => ")",
+            InstructionType::Call => ":",
         };
         write!(f, "{}", symbol)
     }
@@ -64,4 +107,32 @@ impl InstructionType {
     pub fn from_u8(ins: u8) -> Self {
         Self::from_str(&(ins as char).to_string()).expect("Invalid instruction")
     }
+
+    /// Decodes an opcode word, returning `None` for anything that is not a command.
+    pub fn decode(word: FieldElement) -> Option<Self> {
+        let opcode = u8::try_from(word.0).ok()?;
+        Self::from_str(&(opcode as char).to_string()).ok()
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        self.to_string().as_bytes()[0]
+    }
+
+    /// Whether the opcode is followed by a jump target word.
+    pub fn has_argument(&self) -> bool {
+        matches!(
+            self,
+            InstructionType::JumpIfZero
+                | InstructionType::JumpIfNotZero
+                | InstructionType::ProcedureStart
+        )
+    }
+
+    /// Whether the opcode belongs to the pbrain-style procedure extension.
+    pub fn is_extended(&self) -> bool {
+        matches!(
+            self,
+            InstructionType::ProcedureStart | InstructionType::ProcedureEnd | InstructionType::Call
+        )
+    }
 }
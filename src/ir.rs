@@ -0,0 +1,257 @@
+//! Fast execution path: runs of `+ - > <` are folded into one `Instruction` whose
+//! `argument` is the run length, and no trace is recorded. Use `Machine` when the
+//! execution has to be proven.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    io::{Read, Write},
+};
+
+use crate::{
+    crypto::field::FieldElement,
+    instruction::{Instruction, InstructionType},
+};
+
+#[derive(Debug)]
+pub enum IrError {
+    /// Word at `ip` of the compiled program is not a known opcode.
+    InvalidOpcode {
+        ip: usize,
+        word: FieldElement,
+    },
+    /// Loop at `index` of the IR has no matching bracket.
+    UnmatchedLoop {
+        index: usize,
+    },
+    /// Instruction at `index` moved the memory pointer left of cell 0.
+    PointerUnderflow {
+        index: usize,
+    },
+    /// `:` at `index` called a procedure number that was never defined.
+    UndefinedProcedure {
+        index: usize,
+    },
+    Io(std::io::Error),
+}
+
+impl Display for IrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IrError::InvalidOpcode { ip, word } => {
+                write!(f, "invalid opcode {} at address {}", word, ip)
+            }
+            IrError::UnmatchedLoop { index } => write!(f, "unmatched loop at {}", index),
+            IrError::PointerUnderflow { index } => {
+                write!(f, "memory pointer moved left of cell 0 at {}", index)
+            }
+            IrError::UndefinedProcedure { index } => {
+                write!(f, "call to undefined procedure at {}", index)
+            }
+            IrError::Io(err) => write!(f, "io error: {}", err),
+        }
+    }
+}
+
+impl Error for IrError {}
+
+impl From<std::io::Error> for IrError {
+    fn from(err: std::io::Error) -> Self {
+        IrError::Io(err)
+    }
+}
+
+fn is_foldable(ins_type: &InstructionType) -> bool {
+    matches!(
+        ins_type,
+        InstructionType::Right
+            | InstructionType::Left
+            | InstructionType::Plus
+            | InstructionType::Minus
+    )
+}
+
+/// Lowers compiled instructions into run-length encoded IR. Runs longer than
+/// `u8::MAX` are split over several instructions and breakpoints are dropped.
+pub fn lower(code: &[FieldElement]) -> Result<Vec<Instruction>, IrError> {
+    let mut program: Vec<Instruction> = vec![];
+    let mut ip = 0;
+    while ip < code.len() {
+        let ins_type = InstructionType::decode(code[ip])
+            .ok_or(IrError::InvalidOpcode { ip, word: code[ip] })?;
+        ip += if ins_type.has_argument() { 2 } else { 1 };
+
+        if ins_type == InstructionType::Breakpoint {
+            continue;
+        }
+        if is_foldable(&ins_type) {
+            if let Some(last) = program.last_mut() {
+                if last.ins_type == ins_type && last.argument < u8::MAX {
+                    last.argument += 1;
+                    continue;
+                }
+            }
+        }
+        program.push(Instruction {
+            ins_type,
+            argument: 1,
+        });
+    }
+    Ok(program)
+}
+
+/// Index of the matching bracket for every loop and procedure instruction.
+fn jump_table(program: &[Instruction]) -> Result<Vec<usize>, IrError> {
+    let mut table = vec![0; program.len()];
+    let mut stack = vec![];
+    for (index, ins) in program.iter().enumerate() {
+        let opener = match ins.ins_type {
+            InstructionType::JumpIfZero | InstructionType::ProcedureStart => {
+                stack.push((index, ins.ins_type));
+                continue;
+            }
+            InstructionType::JumpIfNotZero => InstructionType::JumpIfZero,
+            InstructionType::ProcedureEnd => InstructionType::ProcedureStart,
+            _ => continue,
+        };
+        match stack.pop() {
+            Some((open, kind)) if kind == opener => {
+                table[open] = index;
+                table[index] = open;
+            }
+            _ => return Err(IrError::UnmatchedLoop { index }),
+        }
+    }
+    match stack.pop() {
+        Some((index, _)) => Err(IrError::UnmatchedLoop { index }),
+        None => Ok(table),
+    }
+}
+
+/// Runs IR on field element cells, i.e. like `Machine` with `CellMode::Field`, growing
+/// the tape on demand. Programs that rely on wrapping cells behave differently here.
+pub fn execute<R: Read, W: Write>(
+    program: &[Instruction],
+    input: &mut R,
+    output: &mut W,
+) -> Result<(), IrError> {
+    let jumps = jump_table(program)?;
+    let mut procedures = HashMap::new();
+    let mut call_stack = vec![];
+    let mut ram = vec![FieldElement::zero(); 100];
+    let mut mp = 0usize;
+    let mut pc = 0;
+    while pc < program.len() {
+        let ins = &program[pc];
+        let count = ins.argument as usize;
+        match ins.ins_type {
+            InstructionType::Right => {
+                mp += count;
+                if mp >= ram.len() {
+                    ram.resize(mp + 1, FieldElement::zero());
+                }
+            }
+            InstructionType::Left => {
+                mp = mp
+                    .checked_sub(count)
+                    .ok_or(IrError::PointerUnderflow { index: pc })?;
+            }
+            InstructionType::Plus => ram[mp] += FieldElement::from(count),
+            InstructionType::Minus => ram[mp] -= FieldElement::from(count),
+            InstructionType::PutChar => output.write_all(&[ram[mp].to_usize() as u8])?,
+            InstructionType::ReadChar => {
+                let mut buf = [0; 1];
+                input.read_exact(&mut buf)?;
+                ram[mp] = FieldElement::from(buf[0] as u64);
+            }
+            InstructionType::JumpIfZero => {
+                if ram[mp] == FieldElement::zero() {
+                    pc = jumps[pc];
+                }
+            }
+            InstructionType::JumpIfNotZero => {
+                if ram[mp] != FieldElement::zero() {
+                    pc = jumps[pc];
+                }
+            }
+            InstructionType::Breakpoint => (),
+            InstructionType::ProcedureStart => {
+                procedures.insert(ram[mp], pc + 1);
+                pc = jumps[pc];
+            }
+            InstructionType::Call => {
+                let body = procedures
+                    .get(&ram[mp])
+                    .ok_or(IrError::UndefinedProcedure { index: pc })?;
+                call_stack.push(pc);
+                pc = body - 1;
+            }
+            InstructionType::ProcedureEnd => {
+                pc = call_stack.pop().expect("')' is only reached from a call");
+            }
+        }
+        pc += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn compile(code: &str) -> Vec<FieldElement> {
+    crate::compiler::Compiler::new(code.to_string())
+        .compile()
+        .unwrap()
+}
+
+#[test]
+fn test_lower_folds_runs() {
+    let program = lower(&compile("+++>>[-<<]...")).unwrap();
+    let folded: Vec<(String, u8)> = program
+        .iter()
+        .map(|ins| (ins.ins_type.to_string(), ins.argument))
+        .collect();
+    let expected = [
+        ("+", 3),
+        (">", 2),
+        ("[", 1),
+        ("-", 1),
+        ("<", 2),
+        ("]", 1),
+        (".", 1),
+        (".", 1),
+        (".", 1),
+    ];
+    let expected: Vec<(String, u8)> = expected
+        .iter()
+        .map(|(symbol, count)| (symbol.to_string(), *count))
+        .collect();
+    assert_eq!(folded, expected);
+}
+
+#[test]
+fn test_lower_splits_long_runs() {
+    let program = lower(&compile(&"+".repeat(600))).unwrap();
+    let counts: Vec<u8> = program.iter().map(|ins| ins.argument).collect();
+    assert_eq!(counts, vec![255, 255, 90]);
+}
+
+#[test]
+fn test_execute_hello_world() {
+    let program = lower(&compile(include_str!("../examples/1.bf"))).unwrap();
+    let mut output = vec![];
+    execute(&program, &mut std::io::empty(), &mut output).unwrap();
+    assert_eq!(output, b"Hello World!\n");
+}
+
+#[test]
+fn test_execute_echo_and_errors() {
+    let program = lower(&compile(",[.,]")).unwrap();
+    let mut output = vec![];
+    let err = execute(&program, &mut &b"abc"[..], &mut output).unwrap_err();
+    assert!(matches!(err, IrError::Io(_)));
+    assert_eq!(output, b"abc");
+
+    let program = lower(&compile("+<")).unwrap();
+    let err = execute(&program, &mut std::io::empty(), &mut vec![]).unwrap_err();
+    assert!(matches!(err, IrError::PointerUnderflow { index: 1 }));
+}
@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use super::parser::{Expr, Stmt};
+
+/// Emits Brainfuck for a parsed program. Every variable owns one cell, temporaries are
+/// allocated above them and are always zero again once released.
+pub struct CodeGen {
+    code: String,
+    vars: HashMap<String, usize>,
+    /// First cell after the variables, temporaries grow from here.
+    temp_base: usize,
+    temps_in_use: usize,
+    ptr: usize,
+}
+
+fn declare(name: &str, vars: &mut HashMap<String, usize>) {
+    let next = vars.len();
+    vars.entry(name.to_string()).or_insert(next);
+}
+
+fn collect_expr_vars(expr: &Expr, vars: &mut HashMap<String, usize>) {
+    match expr {
+        Expr::Number(_) => (),
+        Expr::Var(name) => declare(name, vars),
+        Expr::Add(lhs, rhs) | Expr::Sub(lhs, rhs) => {
+            collect_expr_vars(lhs, vars);
+            collect_expr_vars(rhs, vars);
+        }
+    }
+}
+
+/// Gives every variable a cell in order of first appearance.
+fn collect_vars(stmts: &[Stmt], vars: &mut HashMap<String, usize>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Assign(name, expr)
+            | Stmt::AddAssign(name, expr)
+            | Stmt::SubAssign(name, expr) => {
+                declare(name, vars);
+                collect_expr_vars(expr, vars);
+            }
+            Stmt::Read(name) => declare(name, vars),
+            Stmt::While(expr, body) | Stmt::If(expr, body) => {
+                collect_expr_vars(expr, vars);
+                collect_vars(body, vars);
+            }
+            Stmt::Print(expr) => collect_expr_vars(expr, vars),
+            Stmt::PrintStr(_) => (),
+        }
+    }
+}
+
+impl CodeGen {
+    pub fn new(program: &[Stmt]) -> Self {
+        let mut vars = HashMap::new();
+        collect_vars(program, &mut vars);
+        let temp_base = vars.len();
+        Self {
+            code: String::new(),
+            vars,
+            temp_base,
+            temps_in_use: 0,
+            ptr: 0,
+        }
+    }
+
+    pub fn generate(mut self, program: &[Stmt]) -> String {
+        for stmt in program {
+            self.stmt(stmt);
+        }
+        self.code
+    }
+
+    fn var(&self, name: &str) -> usize {
+        self.vars[name]
+    }
+
+    fn alloc(&mut self) -> usize {
+        self.temps_in_use += 1;
+        self.temp_base + self.temps_in_use - 1
+    }
+
+    /// Releases the most recent temporary, which the caller has left at zero.
+    fn free(&mut self) {
+        self.temps_in_use -= 1;
+    }
+
+    fn move_to(&mut self, cell: usize) {
+        if cell > self.ptr {
+            self.code.push_str(&">".repeat(cell - self.ptr));
+        } else {
+            self.code.push_str(&"<".repeat(self.ptr - cell));
+        }
+        self.ptr = cell;
+    }
+
+    fn emit(&mut self, cell: usize, code: &str) {
+        self.move_to(cell);
+        self.code.push_str(code);
+    }
+
+    fn clear(&mut self, cell: usize) {
+        self.emit(cell, "[-]");
+    }
+
+    /// `dst += src` (or `-=` when `negate`), keeping `src` intact.
+    fn copy_add(&mut self, src: usize, dst: usize, negate: bool) {
+        let temp = self.alloc();
+        let op = if negate { "-" } else { "+" };
+        self.emit(src, "[-");
+        self.emit(dst, op);
+        self.emit(temp, "+");
+        self.emit(src, "]");
+        self.emit(temp, "[-");
+        self.emit(src, "+");
+        self.emit(temp, "]");
+        self.free();
+    }
+
+    /// `dst += expr` (or `-=` when `negate`).
+    fn accumulate(&mut self, expr: &Expr, dst: usize, negate: bool) {
+        match expr {
+            Expr::Number(value) => {
+                let op = if negate { "-" } else { "+" };
+                self.emit(dst, &op.repeat(*value as usize));
+            }
+            Expr::Var(name) => self.copy_add(self.var(name), dst, negate),
+            Expr::Add(lhs, rhs) => {
+                self.accumulate(lhs, dst, negate);
+                self.accumulate(rhs, dst, negate);
+            }
+            Expr::Sub(lhs, rhs) => {
+                self.accumulate(lhs, dst, negate);
+                self.accumulate(rhs, dst, !negate);
+            }
+        }
+    }
+
+    /// Evaluates `expr` into a fresh temporary. The caller clears and frees it.
+    fn eval_temp(&mut self, expr: &Expr) -> usize {
+        let temp = self.alloc();
+        self.accumulate(expr, temp, false);
+        temp
+    }
+
+    /// `dst += expr`, going through a temporary when `expr` reads `dst` itself.
+    fn update(&mut self, name: &str, expr: &Expr, negate: bool) {
+        let dst = self.var(name);
+        if !expr.uses(name) {
+            self.accumulate(expr, dst, negate);
+            return;
+        }
+        let temp = self.eval_temp(expr);
+        let op = if negate { "-" } else { "+" };
+        self.emit(temp, "[-");
+        self.emit(dst, op);
+        self.emit(temp, "]");
+        self.free();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Assign(name, expr) => {
+                if expr.uses(name) {
+                    let temp = self.eval_temp(expr);
+                    let dst = self.var(name);
+                    self.clear(dst);
+                    self.emit(temp, "[-");
+                    self.emit(dst, "+");
+                    self.emit(temp, "]");
+                    self.free();
+                } else {
+                    self.clear(self.var(name));
+                    self.accumulate(expr, self.var(name), false);
+                }
+            }
+            Stmt::AddAssign(name, expr) => self.update(name, expr, false),
+            Stmt::SubAssign(name, expr) => self.update(name, expr, true),
+            Stmt::While(Expr::Var(name), body) => {
+                let cond = self.var(name);
+                self.emit(cond, "[");
+                self.block(body);
+                self.emit(cond, "]");
+            }
+            Stmt::While(expr, body) => {
+                let cond = self.eval_temp(expr);
+                self.emit(cond, "[");
+                self.block(body);
+                self.clear(cond);
+                self.accumulate(expr, cond, false);
+                self.emit(cond, "]");
+                self.free();
+            }
+            Stmt::If(expr, body) => {
+                let cond = self.eval_temp(expr);
+                self.emit(cond, "[");
+                self.block(body);
+                self.clear(cond);
+                self.emit(cond, "]");
+                self.free();
+            }
+            Stmt::Print(Expr::Var(name)) => self.emit(self.var(name), "."),
+            Stmt::Print(expr) => {
+                let temp = self.eval_temp(expr);
+                self.emit(temp, ".");
+                self.clear(temp);
+                self.free();
+            }
+            Stmt::PrintStr(value) => {
+                let temp = self.alloc();
+                let mut current = 0u8;
+                for byte in value {
+                    let code = if *byte >= current {
+                        "+".repeat((byte - current) as usize)
+                    } else {
+                        "-".repeat((current - byte) as usize)
+                    };
+                    self.emit(temp, &code);
+                    self.code.push('.');
+                    current = *byte;
+                }
+                self.clear(temp);
+                self.free();
+            }
+            Stmt::Read(name) => self.emit(self.var(name), ","),
+        }
+    }
+
+    fn block(&mut self, body: &[Stmt]) {
+        for stmt in body {
+            self.stmt(stmt);
+        }
+    }
+}
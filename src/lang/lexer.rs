@@ -0,0 +1,114 @@
+use crate::compiler::SourcePosition;
+
+use super::LangError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Ident(String),
+    Number(u64),
+    Str(Vec<u8>),
+    While,
+    If,
+    Print,
+    Read,
+    Assign,
+    PlusAssign,
+    MinusAssign,
+    Plus,
+    Minus,
+    Semicolon,
+    LeftBrace,
+    RightBrace,
+}
+
+pub fn tokenize(source: &str) -> Result<Vec<(Token, SourcePosition)>, LangError> {
+    let mut tokens = vec![];
+    for (line, text) in source.lines().enumerate() {
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let pos = SourcePosition {
+                line: line + 1,
+                column: i + 1,
+            };
+            let symbol = chars[i];
+            let next = chars.get(i + 1).copied();
+            let token = match symbol {
+                _ if symbol.is_whitespace() => {
+                    i += 1;
+                    continue;
+                }
+                '/' if next == Some('/') => break,
+                '=' => Token::Assign,
+                '+' if next == Some('=') => Token::PlusAssign,
+                '-' if next == Some('=') => Token::MinusAssign,
+                '+' => Token::Plus,
+                '-' => Token::Minus,
+                ';' => Token::Semicolon,
+                '{' => Token::LeftBrace,
+                '}' => Token::RightBrace,
+                '\'' => {
+                    // Character literal such as 'A'.
+                    match (next, chars.get(i + 2)) {
+                        (Some(value), Some('\'')) if value.is_ascii() => {
+                            i += 3;
+                            tokens.push((Token::Number(value as u64), pos));
+                            continue;
+                        }
+                        _ => return Err(LangError::new(pos, "invalid character literal")),
+                    }
+                }
+                '"' => {
+                    let end = chars[i + 1..]
+                        .iter()
+                        .position(|symbol| *symbol == '"')
+                        .ok_or_else(|| LangError::new(pos, "unterminated string"))?;
+                    let value: String = chars[i + 1..i + 1 + end].iter().collect();
+                    i += end + 2;
+                    tokens.push((Token::Str(value.replace("\\n", "\n").into_bytes()), pos));
+                    continue;
+                }
+                _ if symbol.is_ascii_digit() => {
+                    let digits: String = chars[i..]
+                        .iter()
+                        .take_while(|symbol| symbol.is_ascii_digit())
+                        .collect();
+                    i += digits.len();
+                    let value = digits
+                        .parse()
+                        .map_err(|_| LangError::new(pos, "number out of range"))?;
+                    tokens.push((Token::Number(value), pos));
+                    continue;
+                }
+                _ if symbol.is_alphabetic() || symbol == '_' => {
+                    let word: String = chars[i..]
+                        .iter()
+                        .take_while(|symbol| symbol.is_alphanumeric() || **symbol == '_')
+                        .collect();
+                    i += word.chars().count();
+                    let token = match word.as_str() {
+                        "while" => Token::While,
+                        "if" => Token::If,
+                        "print" => Token::Print,
+                        "read" => Token::Read,
+                        _ => Token::Ident(word),
+                    };
+                    tokens.push((token, pos));
+                    continue;
+                }
+                _ => {
+                    return Err(LangError::new(
+                        pos,
+                        &format!("unexpected character {:?}", symbol),
+                    ))
+                }
+            };
+            i += match token {
+                Token::PlusAssign | Token::MinusAssign => 2,
+                _ => 1,
+            };
+            tokens.push((token, pos));
+        }
+    }
+    Ok(tokens)
+}
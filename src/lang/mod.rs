@@ -0,0 +1,136 @@
+//! Small structured language that compiles to Brainfuck source for `Compiler`.
+//!
+//! ```text
+//! n = 5;
+//! while n {
+//!     print '0' + n;
+//!     n -= 1;
+//! }
+//! if n { print "unreachable"; }
+//! read c;
+//! c += 1;
+//! print c;
+//! ```
+//!
+//! Variables hold one cell each, start at 0 and are bytes: the generated code clears
+//! and copies cells by counting them down to 0, which only terminates for values that
+//! wrap like bytes. Run it with `CELL_MODE`; with field cells, a value that goes below
+//! 0 becomes p - 1 and the program hangs.
+
+use std::{error::Error, fmt::Display};
+
+use crate::{compiler::SourcePosition, machine::CellMode};
+
+pub mod codegen;
+pub mod lexer;
+pub mod parser;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LangError {
+    pub position: SourcePosition,
+    pub message: String,
+}
+
+impl LangError {
+    pub fn new(position: SourcePosition, message: &str) -> Self {
+        Self {
+            position,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for LangError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+impl Error for LangError {}
+
+/// Cells the generated programs are written for.
+pub const CELL_MODE: CellMode = CellMode::U8;
+
+/// Compiles a program into Brainfuck source for `CELL_MODE` cells.
+pub fn compile(source: &str) -> Result<String, LangError> {
+    let tokens = lexer::tokenize(source)?;
+    let end = SourcePosition {
+        line: source.lines().count().max(1),
+        column: source.lines().last().map_or(0, |line| line.chars().count()) + 1,
+    };
+    let program = parser::parse(tokens, end)?;
+    Ok(codegen::CodeGen::new(&program).generate(&program))
+}
+
+#[cfg(test)]
+fn run(source: &str, input: &[u8]) -> Vec<u8> {
+    use crate::{compiler::Compiler, machine::MachineConfig};
+
+    let code = Compiler::new(compile(source).unwrap()).compile().unwrap();
+    let config = MachineConfig {
+        cell_mode: CELL_MODE,
+        ..Default::default()
+    };
+    let run = crate::machine::run_with_config(code, input, config);
+    run.result.unwrap();
+    assert!(!run.trace.is_empty());
+    run.output
+}
+
+#[test]
+fn test_lang_assignment_and_print() {
+    let source = "x = 'A'; y = x + 2; x += y - 'A'; print x; print y; print x - y + 10;";
+    assert_eq!(run(source, b""), b"CC\n");
+}
+
+#[test]
+fn test_lang_self_reference() {
+    let source = "x = 3; x = x + x + 1; x += x; print '0' + x - 10;";
+    // x = 7, then 14, printed as '4'.
+    assert_eq!(run(source, b""), b"4");
+}
+
+#[test]
+fn test_lang_while_and_if() {
+    let source = "
+        // count down from 5
+        n = 5;
+        while n {
+            print '0' + n;
+            n -= 1;
+        }
+        if n { print \"unreachable\"; }
+        m = 2;
+        if m - 1 { print \"!\\n\"; }
+        while m - 1 { m -= 1; print \"?\"; }
+    ";
+    assert_eq!(run(source, b""), b"54321!\n?");
+}
+
+#[test]
+fn test_lang_underflow_wraps_like_a_byte() {
+    // 0 - 1 is 255, so the loop runs 255 times and the clear in `x = 'a'` terminates.
+    let source =
+        "x -= 1; n = 0; while x { x -= 1; n += 1; } print n + 1; x = 1 - 2; print x + 'b';";
+    assert_eq!(run(source, b""), b"\0a");
+}
+
+#[test]
+fn test_lang_read() {
+    assert_eq!(
+        run("read c; c += 1; print c; read c; print c;", b"ab"),
+        b"bb"
+    );
+}
+
+#[test]
+fn test_lang_errors() {
+    let err = compile("x = 1;\nwhile x {\n  print x\n}").unwrap_err();
+    assert_eq!(err.to_string(), "4:1: expected ';'");
+    let err = compile("x = 300;").unwrap_err();
+    assert_eq!(err.to_string(), "1:5: number does not fit in a byte");
+    let err = compile("x = 1; y").unwrap_err();
+    assert_eq!(err.to_string(), "1:9: expected '=', '+=' or '-='");
+    let err = compile("x = #;").unwrap_err();
+    assert_eq!(err.to_string(), "1:5: unexpected character '#'");
+}
@@ -0,0 +1,160 @@
+use crate::compiler::SourcePosition;
+
+use super::{lexer::Token, LangError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(u64),
+    Var(String),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn uses(&self, name: &str) -> bool {
+        match self {
+            Expr::Number(_) => false,
+            Expr::Var(var) => var == name,
+            Expr::Add(lhs, rhs) | Expr::Sub(lhs, rhs) => lhs.uses(name) || rhs.uses(name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stmt {
+    Assign(String, Expr),
+    AddAssign(String, Expr),
+    SubAssign(String, Expr),
+    While(Expr, Vec<Stmt>),
+    If(Expr, Vec<Stmt>),
+    Print(Expr),
+    PrintStr(Vec<u8>),
+    Read(String),
+}
+
+struct Parser {
+    tokens: Vec<(Token, SourcePosition)>,
+    index: usize,
+    /// Position just past the last token, used for errors at the end of input.
+    end: SourcePosition,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(token, _)| token)
+    }
+
+    fn position(&self) -> SourcePosition {
+        self.tokens
+            .get(self.index)
+            .map(|(_, pos)| *pos)
+            .unwrap_or(self.end)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.index += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<(), LangError> {
+        let pos = self.position();
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(LangError::new(pos, &format!("expected {}", what))),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, LangError> {
+        let pos = self.position();
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(name),
+            _ => Err(LangError::new(pos, "expected variable name")),
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, LangError> {
+        self.expect(Token::LeftBrace, "'{'")?;
+        let mut body = vec![];
+        while self.peek() != Some(&Token::RightBrace) {
+            if self.peek().is_none() {
+                return Err(LangError::new(self.position(), "expected '}'"));
+            }
+            body.push(self.stmt()?);
+        }
+        self.index += 1;
+        Ok(body)
+    }
+
+    fn stmt(&mut self) -> Result<Stmt, LangError> {
+        let pos = self.position();
+        let stmt = match self.next() {
+            Some(Token::While) => return Ok(Stmt::While(self.expr()?, self.block()?)),
+            Some(Token::If) => return Ok(Stmt::If(self.expr()?, self.block()?)),
+            Some(Token::Print) => match self.peek() {
+                Some(Token::Str(value)) => {
+                    let value = value.clone();
+                    self.index += 1;
+                    Stmt::PrintStr(value)
+                }
+                _ => Stmt::Print(self.expr()?),
+            },
+            Some(Token::Read) => Stmt::Read(self.ident()?),
+            Some(Token::Ident(name)) => {
+                let pos = self.position();
+                match self.next() {
+                    Some(Token::Assign) => Stmt::Assign(name, self.expr()?),
+                    Some(Token::PlusAssign) => Stmt::AddAssign(name, self.expr()?),
+                    Some(Token::MinusAssign) => Stmt::SubAssign(name, self.expr()?),
+                    _ => return Err(LangError::new(pos, "expected '=', '+=' or '-='")),
+                }
+            }
+            _ => return Err(LangError::new(pos, "expected statement")),
+        };
+        self.expect(Token::Semicolon, "';'")?;
+        Ok(stmt)
+    }
+
+    fn expr(&mut self) -> Result<Expr, LangError> {
+        let mut expr = self.term()?;
+        loop {
+            match self.peek() {
+                Some(Token::Plus) => {
+                    self.index += 1;
+                    expr = Expr::Add(Box::new(expr), Box::new(self.term()?));
+                }
+                Some(Token::Minus) => {
+                    self.index += 1;
+                    expr = Expr::Sub(Box::new(expr), Box::new(self.term()?));
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, LangError> {
+        let pos = self.position();
+        match self.next() {
+            Some(Token::Number(value)) if value <= u8::MAX as u64 => Ok(Expr::Number(value)),
+            Some(Token::Number(_)) => Err(LangError::new(pos, "number does not fit in a byte")),
+            Some(Token::Ident(name)) => Ok(Expr::Var(name)),
+            _ => Err(LangError::new(pos, "expected number or variable")),
+        }
+    }
+}
+
+pub fn parse(
+    tokens: Vec<(Token, SourcePosition)>,
+    end: SourcePosition,
+) -> Result<Vec<Stmt>, LangError> {
+    let mut parser = Parser {
+        tokens,
+        index: 0,
+        end,
+    };
+    let mut program = vec![];
+    while parser.peek().is_some() {
+        program.push(parser.stmt()?);
+    }
+    Ok(program)
+}
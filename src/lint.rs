@@ -0,0 +1,313 @@
+use std::fmt::Display;
+
+use crate::{
+    compiler::{SourceMap, SourcePosition},
+    crypto::field::FieldElement,
+    instruction::{DecodeError, InstructionType},
+    machine::CellMode,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LintKind {
+    /// Loop body that never changes the current cell, such as `[]` or `[+-]`, counting in
+    /// the cell mode so that 256 `+` is no change for `CellMode::U8`.
+    InfiniteLoop,
+    /// Straight-line code that moves the memory pointer left of cell 0.
+    PointerUnderflow,
+    /// Loop right after another loop, where the current cell is always zero.
+    DeadLoop,
+    /// Loop body whose pointer moves do not cancel out.
+    UnbalancedLoop,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub kind: LintKind,
+    /// Address of the offending instruction.
+    pub ip: usize,
+    pub position: Option<SourcePosition>,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.position {
+            Some(pos) => write!(f, "{}: warning: {}", pos, self.message),
+            None => write!(f, "ip {}: warning: {}", self.ip, self.message),
+        }
+    }
+}
+
+enum Node {
+    Command(usize, InstructionType),
+    Loop {
+        ip: usize,
+        body: Vec<Node>,
+    },
+    /// `(` ... `)`, whose body only runs when called.
+    Procedure {
+        body: Vec<Node>,
+    },
+}
+
+fn parse(code: &[FieldElement]) -> Result<Vec<Node>, DecodeError> {
+    let mut stack: Vec<(usize, InstructionType, Vec<Node>)> = vec![];
+    let mut nodes = vec![];
+    let mut ip = 0;
+    while ip < code.len() {
+        let ins_type = InstructionType::decode(code[ip])
+            .ok_or(DecodeError::InvalidOpcode { ip, word: code[ip] })?;
+        match ins_type {
+            InstructionType::JumpIfZero | InstructionType::ProcedureStart => {
+                stack.push((ip, ins_type, std::mem::take(&mut nodes)))
+            }
+            InstructionType::JumpIfNotZero | InstructionType::ProcedureEnd => {
+                let (open_ip, kind, outer) =
+                    stack.pop().ok_or(DecodeError::UnmatchedBracket { ip })?;
+                let body = std::mem::replace(&mut nodes, outer);
+                match (kind, ins_type) {
+                    (InstructionType::JumpIfZero, InstructionType::JumpIfNotZero) => {
+                        nodes.push(Node::Loop { ip: open_ip, body })
+                    }
+                    (InstructionType::ProcedureStart, InstructionType::ProcedureEnd) => {
+                        nodes.push(Node::Procedure { body })
+                    }
+                    _ => return Err(DecodeError::UnmatchedBracket { ip }),
+                }
+            }
+            ins_type => nodes.push(Node::Command(ip, ins_type)),
+        }
+        ip += if ins_type.has_argument() { 2 } else { 1 };
+    }
+    match stack.pop() {
+        Some((ip, _, _)) => Err(DecodeError::UnmatchedBracket { ip }),
+        None => Ok(nodes),
+    }
+}
+
+/// Net pointer movement of `nodes`, or `None` when a nested loop makes it unknown.
+fn net_move(nodes: &[Node]) -> Option<isize> {
+    let mut pos = 0isize;
+    for node in nodes {
+        match node {
+            Node::Command(_, InstructionType::Right) => pos += 1,
+            Node::Command(_, InstructionType::Left) => pos -= 1,
+            Node::Loop { body, .. } if net_move(body) != Some(0) => return None,
+            // Calls run code elsewhere, which may move the pointer.
+            Node::Command(_, InstructionType::Call) => return None,
+            _ => (),
+        }
+    }
+    Some(pos)
+}
+
+struct Linter<'a> {
+    source_map: Option<&'a SourceMap>,
+    cell_mode: CellMode,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, kind: LintKind, ip: usize, message: &str) {
+        self.diagnostics.push(Diagnostic {
+            kind,
+            ip,
+            position: self.source_map.and_then(|map| map.get(ip)),
+            message: message.to_string(),
+        });
+    }
+
+    fn check_loop(&mut self, ip: usize, body: &[Node]) {
+        let only_cell_updates = body.iter().all(|node| {
+            matches!(
+                node,
+                Node::Command(_, InstructionType::Plus | InstructionType::Minus)
+            )
+        });
+        if only_cell_updates {
+            let delta = body
+                .iter()
+                .fold(FieldElement::zero(), |delta, node| match node {
+                    Node::Command(_, InstructionType::Plus) => self.cell_mode.increment(delta),
+                    _ => self.cell_mode.decrement(delta),
+                });
+            if delta == FieldElement::zero() {
+                self.report(
+                    LintKind::InfiniteLoop,
+                    ip,
+                    "loop never changes the current cell and cannot terminate once entered",
+                );
+            }
+        }
+
+        let only_moves = body.iter().all(|node| {
+            matches!(
+                node,
+                Node::Command(_, InstructionType::Right | InstructionType::Left)
+            )
+        });
+        match net_move(body) {
+            // Scan loops such as `[>]` move on purpose.
+            Some(moved) if moved != 0 && !only_moves => self.report(
+                LintKind::UnbalancedLoop,
+                ip,
+                &format!(
+                    "loop body moves the memory pointer by {} per iteration",
+                    moved
+                ),
+            ),
+            _ => (),
+        }
+    }
+
+    /// `pos` is the absolute memory pointer when it is known and the code always runs.
+    fn walk(&mut self, nodes: &[Node], mut pos: Option<isize>) {
+        let mut after_loop = false;
+        for node in nodes {
+            match node {
+                Node::Command(_, InstructionType::Breakpoint) => (),
+                Node::Command(_, InstructionType::Call) => {
+                    after_loop = false;
+                    pos = None;
+                }
+                // Procedures run with whatever pointer their callers have.
+                Node::Procedure { body } => self.walk(body, None),
+                Node::Command(ip, ins_type) => {
+                    after_loop = false;
+                    let step = match ins_type {
+                        InstructionType::Right => 1,
+                        InstructionType::Left => -1,
+                        _ => continue,
+                    };
+                    pos = pos.map(|pos| pos + step);
+                    if pos == Some(-1) {
+                        self.report(
+                            LintKind::PointerUnderflow,
+                            *ip,
+                            "memory pointer moves left of cell 0",
+                        );
+                        pos = None;
+                    }
+                }
+                Node::Loop { ip, body } => {
+                    if after_loop {
+                        self.report(
+                            LintKind::DeadLoop,
+                            *ip,
+                            "loop never runs, the current cell is zero after the previous loop",
+                        );
+                    }
+                    self.check_loop(*ip, body);
+                    // The body may not run at all, so nothing inside it is certain.
+                    self.walk(body, None);
+                    if net_move(body) != Some(0) {
+                        pos = None;
+                    }
+                    after_loop = true;
+                }
+            }
+        }
+    }
+}
+
+/// Runs every check over a compiled program that runs with `cell_mode`. Diagnostics carry
+/// source positions when a source map is given.
+pub fn lint(
+    code: &[FieldElement],
+    source_map: Option<&SourceMap>,
+    cell_mode: CellMode,
+) -> Result<Vec<Diagnostic>, DecodeError> {
+    let nodes = parse(code)?;
+    let mut linter = Linter {
+        source_map,
+        cell_mode,
+        diagnostics: vec![],
+    };
+    linter.walk(&nodes, Some(0));
+    linter.diagnostics.sort_by_key(|diagnostic| diagnostic.ip);
+    Ok(linter.diagnostics)
+}
+
+#[cfg(test)]
+fn lint_source(code: &str) -> Vec<(LintKind, Option<SourcePosition>)> {
+    let mut compiler = crate::compiler::Compiler::new(code.to_string());
+    let ins = compiler.compile().unwrap();
+    lint(&ins, Some(&compiler.source_map()), CellMode::Field)
+        .unwrap()
+        .into_iter()
+        .map(|diagnostic| (diagnostic.kind, diagnostic.position))
+        .collect()
+}
+
+#[cfg(test)]
+fn at(line: usize, column: usize) -> Option<SourcePosition> {
+    Some(SourcePosition { line, column })
+}
+
+#[test]
+fn test_lint_clean_program() {
+    assert_eq!(lint_source(include_str!("../examples/1.bf")), vec![]);
+    assert_eq!(lint_source("+[->+<]>[>]<."), vec![]);
+}
+
+#[test]
+fn test_lint_infinite_loop() {
+    assert_eq!(
+        lint_source("+[]+[+-]"),
+        vec![
+            (LintKind::InfiniteLoop, at(1, 2)),
+            (LintKind::InfiniteLoop, at(1, 5)),
+        ]
+    );
+
+    // 256 increments wrap a byte cell back to where it was.
+    let ins = crate::compiler::Compiler::new(format!("+[{}]", "+".repeat(256)))
+        .compile()
+        .unwrap();
+    assert_eq!(lint(&ins, None, CellMode::Field).unwrap(), vec![]);
+    let diagnostics = lint(&ins, None, CellMode::U8).unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].kind, LintKind::InfiniteLoop);
+}
+
+#[test]
+fn test_lint_pointer_underflow() {
+    assert_eq!(
+        lint_source(">+<\n<+"),
+        vec![(LintKind::PointerUnderflow, at(2, 1))]
+    );
+    // Only reported when the move provably runs.
+    assert_eq!(lint_source("+[<+>-]"), vec![]);
+}
+
+#[test]
+fn test_lint_dead_loop_and_unbalanced_loop() {
+    assert_eq!(
+        lint_source("+[-][>+<-]"),
+        vec![(LintKind::DeadLoop, at(1, 5))]
+    );
+    assert_eq!(
+        lint_source("+[>+]"),
+        vec![(LintKind::UnbalancedLoop, at(1, 2))]
+    );
+}
+
+#[test]
+fn test_lint_rejects_invalid_bytecode() {
+    let word = |symbol: char| FieldElement::from(symbol as u64);
+    assert_eq!(
+        lint(&[word('+'), FieldElement::from(7)], None, CellMode::Field),
+        Err(DecodeError::InvalidOpcode {
+            ip: 1,
+            word: FieldElement::from(7)
+        })
+    );
+    assert_eq!(
+        lint(
+            &[word('+'), word(']'), FieldElement::zero()],
+            None,
+            CellMode::Field
+        ),
+        Err(DecodeError::UnmatchedBracket { ip: 1 })
+    );
+}
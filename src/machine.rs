@@ -1,92 +1,498 @@
 use std::{
+    collections::HashMap,
     error::Error,
-    io::{Read, Stdin, Stdout, Write},
+    fmt::Display,
+    io::{Read, Write},
     str::FromStr,
 };
 
-use crate::{crypto::field::FieldElement, instruction::InstructionType, registers::Registers};
+use crate::{
+    bytecode,
+    compiler::{SourceMap, SourcePosition},
+    crypto::{field::FieldElement, sha256::sha256},
+    instruction::InstructionType,
+    registers::{CallRegisters, Registers},
+};
 
 pub struct ProgramMemory {
+    /// Program without `#` breakpoints, which is what the trace and digest describe.
     code: Vec<FieldElement>,
+    /// Address in the given code of every word of `code`, plus its length.
+    addresses: Vec<usize>,
+    /// Number of breakpoints in front of each address of `code` that has any.
+    breakpoints: HashMap<usize, usize>,
+    /// Source positions of the given code, for errors.
+    source_map: SourceMap,
+    /// Public identifier of `code`, see `bytecode::program_digest`.
+    digest: [u8; 32],
+}
+
+/// Compiled program with its `#` breakpoints taken out, see `strip_breakpoints`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StrippedProgram {
+    /// The program a build without `debug` compiles to.
+    pub code: Vec<FieldElement>,
+    /// Address in the original code of every word of `code`, plus the original length.
+    pub addresses: Vec<usize>,
+    /// Addresses in `code` that a breakpoint stood in front of, once per breakpoint.
+    pub breakpoints: Vec<usize>,
+}
+
+/// Removes `#` breakpoints from `code` and moves jump targets to match, so a debug build
+/// runs, traces and hashes exactly like the program compiled without `debug`.
+pub fn strip_breakpoints(code: &[FieldElement]) -> StrippedProgram {
+    // New address of every old address, breakpoints mapping to the word after them.
+    let mut new_address = Vec::with_capacity(code.len() + 1);
+    let mut stripped = StrippedProgram {
+        code: vec![],
+        addresses: vec![],
+        breakpoints: vec![],
+    };
+    let mut arguments = vec![];
+    let mut ip = 0;
+    while ip < code.len() {
+        let ins_type = InstructionType::decode(code[ip]);
+        new_address.push(stripped.code.len());
+        if ins_type == Some(InstructionType::Breakpoint) {
+            stripped.breakpoints.push(stripped.code.len());
+            ip += 1;
+            continue;
+        }
+        stripped.addresses.push(ip);
+        stripped.code.push(code[ip]);
+        ip += 1;
+        if ins_type.is_some_and(|ins_type| ins_type.has_argument()) && ip < code.len() {
+            new_address.push(stripped.code.len());
+            arguments.push(stripped.code.len());
+            stripped.addresses.push(ip);
+            stripped.code.push(code[ip]);
+            ip += 1;
+        }
+    }
+    new_address.push(stripped.code.len());
+    stripped.addresses.push(code.len());
+    for index in arguments {
+        let target = stripped.code[index].to_usize();
+        if let Some(address) = new_address.get(target) {
+            stripped.code[index] = FieldElement::from(*address);
+        }
+    }
+    stripped
+}
+
+#[derive(Debug)]
+pub enum MachineErrorKind {
+    /// `<` on cell 0.
+    PointerUnderflow,
+    /// `>` past the last cell the tape may have.
+    TapeOverflow {
+        tape_size: usize,
+    },
+    /// Word at `ip` that is not an instruction.
+    InvalidOpcode(FieldElement),
+    /// Jump instruction at the end of the code, without its target word.
+    MissingArgument,
+    /// `:` on a cell no procedure was defined for.
+    UndefinedProcedure(FieldElement),
+    /// `)` reached outside a procedure call.
+    ReturnOutsideProcedure,
+    /// `,` after the last input byte, with `EofPolicy::Error`.
+    EndOfInput,
+    Io(std::io::Error),
+}
+
+impl Display for MachineErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineErrorKind::PointerUnderflow => write!(f, "memory pointer moved left of cell 0"),
+            MachineErrorKind::TapeOverflow { tape_size } => {
+                write!(
+                    f,
+                    "memory pointer moved past the end of the {}-cell tape",
+                    tape_size
+                )
+            }
+            MachineErrorKind::InvalidOpcode(word) => write!(f, "invalid opcode {}", word),
+            MachineErrorKind::MissingArgument => write!(f, "jump target missing"),
+            MachineErrorKind::UndefinedProcedure(number) => {
+                write!(f, "call to undefined procedure {}", number)
+            }
+            MachineErrorKind::ReturnOutsideProcedure => write!(f, "')' outside a procedure"),
+            MachineErrorKind::EndOfInput => write!(f, "read past the end of input"),
+            MachineErrorKind::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for MachineErrorKind {
+    fn from(err: std::io::Error) -> Self {
+        MachineErrorKind::Io(err)
+    }
+}
+
+/// Error that stopped a `Machine`, at the instruction it was executing.
+#[derive(Debug)]
+pub struct MachineError {
+    pub kind: MachineErrorKind,
+    pub clk: usize,
+    pub ip: usize,
+    /// Source position of the instruction, when the machine has a source map.
+    pub position: Option<SourcePosition>,
+}
+
+impl Display for MachineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(position) = self.position {
+            write!(f, "{}: ", position)?;
+        }
+        write!(f, "{} at clk {}, ip {}", self.kind, self.clk, self.ip)
+    }
+}
+
+impl Error for MachineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            MachineErrorKind::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// What a tape cell holds. `+` and `-` wrap around within the cell type, and the mode
+/// is part of the statement a proof makes about the trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CellMode {
+    /// Goldilocks field elements, so `-` on 0 gives p - 1.
+    #[default]
+    Field,
+    U8,
+    U16,
+    U32,
+}
+
+impl CellMode {
+    /// Largest value a cell can hold, `None` for field elements.
+    pub fn max_value(&self) -> Option<u64> {
+        match self {
+            CellMode::Field => None,
+            CellMode::U8 => Some(u8::MAX as u64),
+            CellMode::U16 => Some(u16::MAX as u64),
+            CellMode::U32 => Some(u32::MAX as u64),
+        }
+    }
+
+    pub fn increment(&self, value: FieldElement) -> FieldElement {
+        match self.max_value() {
+            None => value + FieldElement::one(),
+            Some(max) => FieldElement::from((value.0 + 1) & max),
+        }
+    }
+
+    pub fn decrement(&self, value: FieldElement) -> FieldElement {
+        match self.max_value() {
+            None => value - FieldElement::one(),
+            Some(max) => FieldElement::from(value.0.wrapping_sub(1) & max),
+        }
+    }
+}
+
+impl Display for CellMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            CellMode::Field => "field",
+            CellMode::U8 => "u8",
+            CellMode::U16 => "u16",
+            CellMode::U32 => "u32",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for CellMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "field" => Ok(CellMode::Field),
+            "u8" => Ok(CellMode::U8),
+            "u16" => Ok(CellMode::U16),
+            "u32" => Ok(CellMode::U32),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What `,` stores once the input is exhausted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EofPolicy {
+    Zero,
+    /// -1 in the cell type, e.g. 255 for `CellMode::U8`.
+    MinusOne,
+    /// Leaves the cell as it was.
+    Unchanged,
+    /// Stops with `MachineErrorKind::EndOfInput`.
+    #[default]
+    Error,
+}
+
+impl Display for EofPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            EofPolicy::Zero => "zero",
+            EofPolicy::MinusOne => "minus-one",
+            EofPolicy::Unchanged => "unchanged",
+            EofPolicy::Error => "error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for EofPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zero" => Ok(EofPolicy::Zero),
+            "minus-one" => Ok(EofPolicy::MinusOne),
+            "unchanged" => Ok(EofPolicy::Unchanged),
+            "error" => Ok(EofPolicy::Error),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Tape, cell and input settings of a `Machine`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineConfig {
+    /// Cells allocated before the program starts, at least one.
+    pub tape_size: usize,
+    /// Whether the tape grows when the memory pointer moves past its end.
+    pub grow_tape: bool,
+    /// Most cells a growing tape may have.
+    pub max_tape_size: usize,
+    pub cell_mode: CellMode,
+    pub eof: EofPolicy,
+}
+
+impl Default for MachineConfig {
+    /// A small tape of field elements growing on demand up to the classic 30,000 cells,
+    /// failing on a read past the end of input.
+    fn default() -> Self {
+        Self {
+            tape_size: 100,
+            grow_tape: true,
+            max_tape_size: 30_000,
+            cell_mode: CellMode::Field,
+            eof: EofPolicy::Error,
+        }
+    }
 }
 
 pub struct MutableState {
     ram: Vec<FieldElement>,
     registers: Registers,
+    /// Return addresses of the procedures being run, innermost last.
+    call_stack: Vec<FieldElement>,
+    /// Body address of every defined procedure, by number.
+    procedures: HashMap<FieldElement, FieldElement>,
 }
 
-pub struct IO {
-    input: Box<dyn Read>,
-    output: Box<dyn Write>,
+pub struct IO<R, W, D> {
+    input: R,
+    output: W,
+    debug: D,
 }
 
-pub struct Machine {
+/// Cells on each side of the memory pointer shown at a breakpoint.
+const DEBUG_CELLS: usize = 4;
+
+/// Runs a compiled program, reading `,` from `R` and writing `.` to `W`, e.g. stdin
+/// and stdout, or a byte slice and a `Vec<u8>` to run on fixed input and capture the
+/// output.
+/// Breakpoints write to `D`, stderr unless replaced with `with_debug_output`.
+pub struct Machine<R = Box<dyn Read>, W = Box<dyn Write>, D = Box<dyn Write>> {
+    config: MachineConfig,
     program: ProgramMemory,
     state: MutableState,
-    io: IO,
+    io: IO<R, W, D>,
     trace: Vec<Registers>,
+    /// Value every `,` stored, in order.
+    input_tape: Vec<FieldElement>,
 }
 
-impl Machine {
-    pub fn new(code: Vec<FieldElement>, input: Stdin, output: Stdout) -> Machine {
+impl<R: Read, W: Write> Machine<R, W> {
+    pub fn new(code: Vec<FieldElement>, input: R, output: W) -> Self {
+        Self::with_config(code, input, output, MachineConfig::default())
+    }
+
+    pub fn with_config(
+        code: Vec<FieldElement>,
+        input: R,
+        output: W,
+        config: MachineConfig,
+    ) -> Self {
+        let stripped = strip_breakpoints(&code);
+        let mut breakpoints = HashMap::new();
+        for ip in stripped.breakpoints {
+            *breakpoints.entry(ip).or_insert(0) += 1;
+        }
+        let code = stripped.code;
+        let mut registers = Registers::new();
+        if uses_procedures(&code) {
+            registers.call = Some(CallRegisters {
+                depth: FieldElement::zero(),
+                ra: FieldElement::zero(),
+            });
+        }
         Machine {
-            program: ProgramMemory { code },
+            program: ProgramMemory {
+                addresses: stripped.addresses,
+                breakpoints,
+                source_map: SourceMap::default(),
+                digest: bytecode::program_digest(&code),
+                code,
+            },
             state: MutableState {
-                ram: vec![FieldElement::zero(); 100],
-                registers: Registers::new(),
+                ram: vec![FieldElement::zero(); config.tape_size.max(1)],
+                registers,
+                call_stack: vec![],
+                procedures: HashMap::new(),
             },
             io: IO {
-                input: Box::new(input),
-                output: Box::new(output),
+                input,
+                output,
+                debug: Box::new(std::io::stderr()),
             },
             trace: vec![],
+            input_tape: vec![],
+            config,
         }
     }
+}
 
-    pub fn execute(&mut self) -> Result<(), Box<dyn Error>> {
-        // =============================
-        // First clock cycle
-        // =============================
-        self.state.registers.ci = self.program.code[self.state.registers.ip.to_usize()];
-        self.state.registers.ni = self.program.code[self.state.registers.ip.to_usize() + 1];
-        let target_ci = self.state.registers.ci;
-        let ins_type =
-            InstructionType::from_str(&(target_ci.to_usize() as u8 as char).to_string()).unwrap();
-        self.write_trace();
-        self.execute_instruction(ins_type)?;
+impl<R: Read, W: Write, D: Write> Machine<R, W, D> {
+    /// Machine whose `#` breakpoints dump the machine state to `debug`.
+    pub fn with_debug_output<E: Write>(self, debug: E) -> Machine<R, W, E> {
+        Machine {
+            config: self.config,
+            program: self.program,
+            state: self.state,
+            io: IO {
+                input: self.io.input,
+                output: self.io.output,
+                debug,
+            },
+            trace: self.trace,
+            input_tape: self.input_tape,
+        }
+    }
 
-        while self.state.registers.ip.to_usize() < self.program.code.len() - 1 {
-            // ============================
-            // Middle clock cycles
-            // ============================
-            self.next_clock_cycle();
-            self.state.registers.ci = self.program.code[self.state.registers.ip.to_usize()];
-            self.state.registers.ni =
-                if self.state.registers.ip.to_usize() == self.program.code.len() - 1 {
-                    FieldElement::zero()
-                } else {
-                    self.program.code[self.state.registers.ip.to_usize() + 1]
-                };
+    pub fn debug_output(&self) -> &D {
+        &self.io.debug
+    }
+
+    /// Positions that errors report, e.g. `Compiler::source_map`. It maps addresses of the
+    /// code the machine was given, breakpoints included.
+    pub fn set_source_map(&mut self, source_map: SourceMap) {
+        self.program.source_map = source_map;
+    }
+
+    /// Runs the program to the end. On error the trace keeps every row up to and
+    /// including the failing instruction.
+    pub fn execute(&mut self) -> Result<(), MachineError> {
+        self.run().map_err(|kind| {
+            let ip = self.state.registers.ip.to_usize();
+            MachineError {
+                kind,
+                clk: self.state.registers.clk.to_usize(),
+                ip,
+                position: self
+                    .code_address(ip)
+                    .and_then(|address| self.program.source_map.get(address)),
+            }
+        })
+    }
+
+    fn run(&mut self) -> Result<(), MachineErrorKind> {
+        self.dump_breakpoints()?;
+        while self.state.registers.ip.to_usize() < self.program.code.len() {
+            let ip = self.state.registers.ip.to_usize();
+            self.state.registers.ci = self.program.code[ip];
+            self.state.registers.ni = self
+                .program
+                .code
+                .get(ip + 1)
+                .copied()
+                .unwrap_or(FieldElement::zero());
             self.write_trace();
-            let ins_type = InstructionType::from_u8(self.state.registers.ci.to_usize() as u8);
+            let ins_type = InstructionType::decode(self.state.registers.ci)
+                .ok_or(MachineErrorKind::InvalidOpcode(self.state.registers.ci))?;
             self.execute_instruction(ins_type)?;
+            self.next_clock_cycle();
+            self.dump_breakpoints()?;
         }
 
         // ============================
         // Last clock cycle
         // ============================
-        self.next_clock_cycle();
         self.state.registers.ci = FieldElement::zero();
         self.state.registers.ni = FieldElement::zero();
         self.write_trace();
         Ok(())
     }
 
-    fn read_char(&mut self) -> Result<(), std::io::Error> {
+    /// Dumps the state once for every `#` breakpoint in front of `ip`. Breakpoints are not
+    /// part of the program, so they take no clock cycle and leave no trace row.
+    fn dump_breakpoints(&mut self) -> Result<(), std::io::Error> {
+        let ip = self.state.registers.ip.to_usize();
+        let count = self.program.breakpoints.get(&ip).copied().unwrap_or(0);
+        for _ in 0..count {
+            self.dump_state()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the registers and the tape cells around the memory pointer, the current one
+    /// in brackets.
+    fn dump_state(&mut self) -> Result<(), std::io::Error> {
+        let registers = &self.state.registers;
+        let mp = registers.mp.to_usize();
+        let start = mp.saturating_sub(DEBUG_CELLS);
+        let end = (mp + DEBUG_CELLS + 1).min(self.state.ram.len());
+        let cells: Vec<String> = (start..end)
+            .map(|index| match self.state.ram[index] {
+                value if index == mp => format!("[{}]", value),
+                value => value.to_string(),
+            })
+            .collect();
+        writeln!(
+            self.io.debug,
+            "# breakpoint at ip {}, clk {}, mp {}, mv {}\n  tape {}..{}: {}",
+            registers.ip,
+            registers.clk,
+            registers.mp,
+            registers.mv,
+            start,
+            end,
+            cells.join(" ")
+        )
+    }
+
+    fn read_char(&mut self) -> Result<(), MachineErrorKind> {
+        let mp = self.state.registers.mp.to_usize();
         let mut buf = [0; 1];
-        self.io.input.read_exact(&mut buf)?;
-        let input_char = buf[0] as usize;
-        self.state.ram[self.state.registers.mp.to_usize()] = FieldElement::from(input_char as u64);
+        let value = match self.io.input.read_exact(&mut buf) {
+            Ok(()) => FieldElement::from(buf[0] as u64),
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => match self.config.eof {
+                EofPolicy::Zero => FieldElement::zero(),
+                EofPolicy::MinusOne => self.config.cell_mode.decrement(FieldElement::zero()),
+                EofPolicy::Unchanged => self.state.ram[mp],
+                EofPolicy::Error => return Err(MachineErrorKind::EndOfInput),
+            },
+            Err(err) => return Err(err.into()),
+        };
+        self.state.ram[mp] = value;
+        self.input_tape.push(value);
         Ok(())
     }
 
@@ -96,21 +502,35 @@ impl Machine {
         Ok(())
     }
 
-    fn execute_instruction(&mut self, ins: InstructionType) -> Result<(), Box<dyn Error>> {
+    /// Target word of the jump instruction at `ip`.
+    fn argument(&self) -> Result<FieldElement, MachineErrorKind> {
+        let ip = self.state.registers.ip.to_usize();
+        self.program
+            .code
+            .get(ip + 1)
+            .copied()
+            .ok_or(MachineErrorKind::MissingArgument)
+    }
+
+    fn execute_instruction(&mut self, ins: InstructionType) -> Result<(), MachineErrorKind> {
         match ins {
             InstructionType::Right => {
+                self.ensure_tape(self.state.registers.mp.to_usize() + 1)?;
                 self.state.registers.mp += FieldElement::one();
             }
             InstructionType::Left => {
+                if self.state.registers.mp == FieldElement::zero() {
+                    return Err(MachineErrorKind::PointerUnderflow);
+                }
                 self.state.registers.mp -= FieldElement::one();
             }
             InstructionType::Plus => {
                 let mp = self.state.registers.mp.to_usize();
-                self.state.ram[mp] += FieldElement::one();
+                self.state.ram[mp] = self.config.cell_mode.increment(self.state.ram[mp]);
             }
             InstructionType::Minus => {
                 let mp = self.state.registers.mp.to_usize();
-                self.state.ram[mp] -= FieldElement::one();
+                self.state.ram[mp] = self.config.cell_mode.decrement(self.state.ram[mp]);
             }
             InstructionType::ReadChar => {
                 self.read_char()?;
@@ -120,8 +540,7 @@ impl Machine {
             }
             InstructionType::JumpIfZero => {
                 let mp = self.state.registers.mp.to_usize();
-                let ip = self.state.registers.ip.to_usize();
-                let argument = self.program.code[ip + 1];
+                let argument = self.argument()?;
                 self.state.registers.ni = argument;
                 if self.state.ram[mp] == FieldElement::zero() {
                     self.state.registers.ip = argument;
@@ -131,14 +550,47 @@ impl Machine {
             }
             InstructionType::JumpIfNotZero => {
                 let mp = self.state.registers.mp.to_usize();
-                let ip = self.state.registers.ip.to_usize();
-                let argument = self.program.code[ip + 1];
+                let argument = self.argument()?;
                 if self.state.ram[mp] != FieldElement::zero() {
                     self.state.registers.ip = argument - FieldElement::one();
                     return Ok(());
                 }
                 self.state.registers.ip += FieldElement::one();
             }
+            // Breakpoints are stripped before loading, so this is a jump into a data word.
+            InstructionType::Breakpoint => {
+                return Err(MachineErrorKind::InvalidOpcode(self.state.registers.ci));
+            }
+            InstructionType::ProcedureStart => {
+                let mp = self.state.registers.mp.to_usize();
+                let argument = self.argument()?;
+                let ip = self.state.registers.ip.to_usize();
+                self.state
+                    .procedures
+                    .insert(self.state.ram[mp], FieldElement::from(ip + 2));
+                // Lands on ')' and steps past it on the next cycle.
+                self.state.registers.ip = argument;
+                return Ok(());
+            }
+            InstructionType::Call => {
+                let mp = self.state.registers.mp.to_usize();
+                let Some(body) = self.state.procedures.get(&self.state.ram[mp]).copied() else {
+                    return Err(MachineErrorKind::UndefinedProcedure(self.state.ram[mp]));
+                };
+                let ip = self.state.registers.ip;
+                self.state.call_stack.push(ip + FieldElement::one());
+                self.state.registers.ip = body - FieldElement::one();
+                self.update_call_registers();
+                return Ok(());
+            }
+            InstructionType::ProcedureEnd => {
+                let Some(ra) = self.state.call_stack.pop() else {
+                    return Err(MachineErrorKind::ReturnOutsideProcedure);
+                };
+                self.state.registers.ip = ra - FieldElement::one();
+                self.update_call_registers();
+                return Ok(());
+            }
         }
         self.state.registers.mv = self.state.ram[self.state.registers.mp.to_usize()];
         self.state.registers.mvi = if self.state.registers.mv == FieldElement::zero() {
@@ -150,6 +602,39 @@ impl Machine {
         Ok(())
     }
 
+    /// Grows the tape to cover cell `mp`, or fails when that would exceed the configured
+    /// size.
+    fn ensure_tape(&mut self, mp: usize) -> Result<(), MachineErrorKind> {
+        let len = self.state.ram.len();
+        if mp < len {
+            return Ok(());
+        }
+        let limit = if self.config.grow_tape {
+            self.config.max_tape_size
+        } else {
+            len
+        };
+        if mp >= limit {
+            return Err(MachineErrorKind::TapeOverflow { tape_size: limit });
+        }
+        self.state
+            .ram
+            .resize((len * 2).clamp(mp + 1, limit), FieldElement::zero());
+        Ok(())
+    }
+
+    fn update_call_registers(&mut self) {
+        self.state.registers.call = Some(CallRegisters {
+            depth: FieldElement::from(self.state.call_stack.len()),
+            ra: self
+                .state
+                .call_stack
+                .last()
+                .copied()
+                .unwrap_or(FieldElement::zero()),
+        });
+    }
+
     fn next_clock_cycle(&mut self) {
         self.state.registers.clk += FieldElement::one();
         self.state.registers.ip += FieldElement::one();
@@ -162,4 +647,396 @@ impl Machine {
     pub fn get_trace(&self) -> Vec<Registers> {
         self.trace.clone()
     }
+
+    /// Values the program read, one per executed `,`, including those stored at the end
+    /// of input.
+    pub fn input_tape(&self) -> &[FieldElement] {
+        &self.input_tape
+    }
+
+    /// Address in the code the machine was given of the instruction at `ip`, which differs
+    /// from `ip` when the code has breakpoints. Use it to look up source positions.
+    pub fn code_address(&self, ip: usize) -> Option<usize> {
+        self.program.addresses.get(ip).copied()
+    }
+
+    /// Digest of the program the trace belongs to, which leaves out breakpoints.
+    pub fn program_digest(&self) -> [u8; 32] {
+        self.program.digest
+    }
+
+    /// Digest of the program together with the cell mode it runs with. The same program
+    /// traces differently under another `CellMode`, so this is what identifies a trace.
+    pub fn statement_digest(&self) -> [u8; 32] {
+        let mode = self.config.cell_mode.to_string();
+        sha256(&[&self.program.digest[..], mode.as_bytes()].concat())
+    }
+
+    /// Cells allocated on the tape after the run, which depends on how the tape grew. It
+    /// never exceeds the configured limit; `max_mp` is the bound the trace needs.
+    pub fn tape_size(&self) -> usize {
+        self.state.ram.len()
+    }
+
+    /// Highest `mp` in the trace, 0 for an empty trace.
+    pub fn max_mp(&self) -> usize {
+        self.trace
+            .iter()
+            .map(|registers| registers.mp.to_usize())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn config(&self) -> &MachineConfig {
+        &self.config
+    }
+
+    /// Everything written so far, when `W` keeps it, e.g. `Vec<u8>`.
+    pub fn output(&self) -> &W {
+        &self.io.output
+    }
+
+    pub fn into_output(self) -> W {
+        self.io.output
+    }
+}
+
+/// Whether `code` contains procedure instructions, which add call-stack columns to
+/// the trace. Plain programs keep the original columns.
+fn uses_procedures(code: &[FieldElement]) -> bool {
+    let mut ip = 0;
+    while ip < code.len() {
+        match InstructionType::decode(code[ip]) {
+            Some(ins_type) if ins_type.is_extended() => return true,
+            Some(ins_type) if ins_type.has_argument() => ip += 2,
+            _ => ip += 1,
+        }
+    }
+    false
+}
+
+/// Outcome of running a program in tests.
+#[cfg(test)]
+pub struct TestRun {
+    pub result: Result<(), MachineError>,
+    pub output: Vec<u8>,
+    pub trace: Vec<Registers>,
+}
+
+/// Runs compiled `code` on `input` with the default configuration. Shared by the tests
+/// of every module that checks what a program prints or how long its trace is.
+#[cfg(test)]
+pub fn run_code(code: Vec<FieldElement>, input: &[u8]) -> TestRun {
+    run_with_config(code, input, MachineConfig::default())
+}
+
+#[cfg(test)]
+pub fn run_with_config(code: Vec<FieldElement>, input: &[u8], config: MachineConfig) -> TestRun {
+    let mut machine = Machine::with_config(code, input, Vec::new(), config);
+    let result = machine.execute();
+    let trace = machine.get_trace();
+    TestRun {
+        result,
+        output: machine.into_output(),
+        trace,
+    }
+}
+
+/// `run_code` for Brainfuck source, which must compile.
+#[cfg(test)]
+pub fn run_source(source: &str, input: &[u8]) -> TestRun {
+    let code = crate::compiler::Compiler::new(source.to_string())
+        .compile()
+        .unwrap();
+    run_code(code, input)
+}
+
+#[test]
+fn test_execute_loop_spanning_more_than_255_instructions() {
+    use crate::compiler::Compiler;
+
+    let code = format!(
+        "+++[{}+{}{}-]{}",
+        ">".repeat(50),
+        "<".repeat(50),
+        "+-".repeat(100),
+        ">".repeat(50)
+    );
+    let ins = Compiler::new(code).compile().unwrap();
+    assert!(ins.len() > 255);
+    let mut machine = Machine::new(ins, std::io::empty(), std::io::sink());
+    machine.execute().unwrap();
+    let last = machine.get_trace().last().unwrap().clone();
+    assert_eq!(last.mp, FieldElement::from(50));
+    assert_eq!(last.mv, FieldElement::from(3));
+}
+
+#[test]
+fn test_breakpoint_dumps_state_without_trace_row() {
+    use crate::compiler::{Compiler, CompilerConfig};
+
+    let run = |debug: bool| {
+        let config = CompilerConfig {
+            debug,
+            ..Default::default()
+        };
+        let code = Compiler::with_config("#++>+++#[-]#".to_string(), config)
+            .compile()
+            .unwrap();
+        let mut machine =
+            Machine::new(code, std::io::empty(), std::io::sink()).with_debug_output(Vec::new());
+        machine.execute().unwrap();
+        let dump = String::from_utf8(machine.debug_output().clone()).unwrap();
+        (machine.get_trace(), machine.program_digest(), dump)
+    };
+
+    let (trace, digest, dump) = run(false);
+    assert_eq!(dump, "");
+    let (debug_trace, debug_digest, debug_dump) = run(true);
+    assert_eq!(debug_trace, trace);
+    assert_eq!(debug_digest, digest);
+    assert_eq!(
+        debug_dump,
+        "# breakpoint at ip 0, clk 0, mp 0, mv 0\n  tape 0..5: [0] 0 0 0 0\n\
+         # breakpoint at ip 6, clk 6, mp 1, mv 3\n  tape 0..6: 2 [3] 0 0 0 0\n\
+         # breakpoint at ip 11, clk 13, mp 1, mv 0\n  tape 0..6: 2 [0] 0 0 0 0\n"
+    );
+}
+
+#[test]
+fn test_execute_procedures() {
+    use crate::compiler::{Compiler, CompilerConfig};
+
+    let config = CompilerConfig {
+        extended: true,
+        ..Default::default()
+    };
+    let code = Compiler::with_config("+(>+++<)::>.".to_string(), config.clone())
+        .compile()
+        .unwrap();
+    let mut machine = Machine::new(code, std::io::empty(), Vec::new());
+    machine.execute().unwrap();
+    assert_eq!(machine.output(), &[6]);
+    let trace = machine.get_trace();
+    assert_eq!(trace.len(), 19);
+    let call = |clk: usize| {
+        let call = trace[clk].call.clone().unwrap();
+        (trace[clk].ip.0, call.depth.0, call.ra.0)
+    };
+    // First call from ip 9 runs the body at 3..=8 and returns to 10.
+    assert_eq!(call(2), (9, 0, 0));
+    assert_eq!(call(3), (3, 1, 10));
+    assert_eq!(call(8), (8, 1, 10));
+    assert_eq!(call(9), (10, 0, 0));
+    assert_eq!(call(10), (3, 1, 11));
+    assert_eq!(call(18), (13, 0, 0));
+    assert_eq!(trace[17].mv, FieldElement::from(6));
+
+    // Plain programs keep the original columns, even with the extension enabled.
+    let code = Compiler::with_config("+>+.".to_string(), config)
+        .compile()
+        .unwrap();
+    let mut machine = Machine::new(code, std::io::empty(), std::io::sink());
+    machine.execute().unwrap();
+    assert!(machine.get_trace().iter().all(|row| row.call.is_none()));
+}
+
+#[test]
+fn test_execute_on_fixed_input() {
+    use crate::compiler::Compiler;
+
+    let code = Compiler::new(",[.,]".to_string()).compile().unwrap();
+    let mut machine = Machine::new(code, &b"abc\0"[..], Vec::new());
+    machine.execute().unwrap();
+    assert_eq!(machine.into_output(), b"abc");
+}
+
+#[test]
+fn test_execute_grows_tape() {
+    use crate::compiler::Compiler;
+
+    let source = format!("{}+.", ">".repeat(250));
+    let code = Compiler::new(source).compile().unwrap();
+    let mut machine = Machine::new(code.clone(), std::io::empty(), Vec::new());
+    machine.execute().unwrap();
+    assert_eq!(machine.tape_size(), 400);
+    assert_eq!(machine.max_mp(), 250);
+    assert_eq!(
+        machine.get_trace().last().unwrap().mp,
+        FieldElement::from(250)
+    );
+    assert_eq!(machine.output(), &[1]);
+
+    let config = MachineConfig {
+        tape_size: 30_000,
+        grow_tape: false,
+        ..Default::default()
+    };
+    let mut machine = Machine::with_config(code.clone(), std::io::empty(), Vec::new(), config);
+    machine.execute().unwrap();
+    assert_eq!(machine.tape_size(), 30_000);
+    assert_eq!(machine.max_mp(), 250);
+
+    for config in [
+        MachineConfig {
+            tape_size: 200,
+            grow_tape: false,
+            ..Default::default()
+        },
+        MachineConfig {
+            max_tape_size: 250,
+            ..Default::default()
+        },
+    ] {
+        let mut machine = Machine::with_config(code.clone(), std::io::empty(), Vec::new(), config);
+        let err = machine.execute().unwrap_err();
+        assert!(
+            matches!(err.kind, MachineErrorKind::TapeOverflow { .. }),
+            "{}",
+            err
+        );
+        assert!(machine.tape_size() <= 250);
+        assert!(machine.max_mp() < machine.tape_size());
+    }
+}
+
+#[test]
+fn test_execute_short_programs() {
+    let run = |code: Vec<u64>| {
+        let code = code.into_iter().map(FieldElement::from).collect();
+        let mut machine = Machine::new(code, std::io::empty(), Vec::new());
+        let result = machine.execute();
+        (result, machine.get_trace())
+    };
+
+    let (result, trace) = run(vec![]);
+    result.unwrap();
+    assert_eq!(trace.len(), 1);
+    assert_eq!(trace[0].ci, FieldElement::zero());
+
+    let (result, trace) = run(vec![b'+' as u64]);
+    result.unwrap();
+    assert_eq!(trace.len(), 2);
+    assert_eq!(trace[0].ni, FieldElement::zero());
+    assert_eq!(trace[1].mv, FieldElement::one());
+
+    // A '[' without its target word stops instead of reading past the code.
+    let (result, trace) = run(vec![b'+' as u64, b'[' as u64]);
+    let err = result.unwrap_err();
+    assert!(matches!(err.kind, MachineErrorKind::MissingArgument));
+    assert_eq!((err.clk, err.ip), (1, 1));
+    assert_eq!(trace.len(), 2);
+}
+
+#[test]
+fn test_execute_errors() {
+    use crate::compiler::Compiler;
+
+    let mut compiler = Compiler::new("+>\n<<+".to_string());
+    let code = compiler.compile().unwrap();
+    let mut machine = Machine::new(code, std::io::empty(), std::io::sink());
+    machine.set_source_map(compiler.source_map());
+    let err = machine.execute().unwrap_err();
+    assert!(matches!(err.kind, MachineErrorKind::PointerUnderflow));
+    assert_eq!(
+        err.to_string(),
+        "2:2: memory pointer moved left of cell 0 at clk 3, ip 3"
+    );
+    let trace = machine.get_trace();
+    assert_eq!(trace.len(), 4);
+    assert_eq!(trace[3].mp, FieldElement::zero());
+
+    let code = vec![FieldElement::from(b'+' as u64), FieldElement::from(7)];
+    let mut machine = Machine::new(code, std::io::empty(), std::io::sink());
+    let err = machine.execute().unwrap_err();
+    assert_eq!(err.to_string(), "invalid opcode 7 at clk 1, ip 1");
+    assert!(err.position.is_none());
+
+    // The jump lands on the argument word of `]`, which reads as `#`.
+    let code = [b'[' as u64, 2, b']' as u64, b'#' as u64]
+        .into_iter()
+        .map(FieldElement::from)
+        .collect();
+    let mut machine = Machine::new(code, std::io::empty(), std::io::sink());
+    let err = machine.execute().unwrap_err();
+    assert!(matches!(err.kind, MachineErrorKind::InvalidOpcode(_)));
+    assert_eq!(err.to_string(), "invalid opcode 35 at clk 1, ip 3");
+}
+
+#[test]
+fn test_execute_cell_modes() {
+    use crate::compiler::Compiler;
+
+    // Prints 0 - 1, then 0 - 1 + 2.
+    let code = Compiler::new("-.>-++.".to_string()).compile().unwrap();
+    let run = |cell_mode| {
+        let config = MachineConfig {
+            cell_mode,
+            ..Default::default()
+        };
+        let mut machine = Machine::with_config(code.clone(), std::io::empty(), Vec::new(), config);
+        machine.execute().unwrap();
+        assert_eq!(machine.config().cell_mode, cell_mode);
+        let trace = machine.get_trace();
+        let mv: Vec<u64> = [1, 4].iter().map(|clk| trace[*clk].mv.0).collect();
+        (mv, machine.into_output())
+    };
+
+    let p_minus_one = (FieldElement::zero() - FieldElement::one()).0;
+    assert_eq!(run(CellMode::Field), (vec![p_minus_one; 2], vec![0, 1]));
+    assert_eq!(run(CellMode::U8), (vec![255, 255], vec![255, 1]));
+    assert_eq!(run(CellMode::U16), (vec![65535, 65535], vec![255, 1]));
+    assert_eq!(
+        run(CellMode::U32),
+        (vec![u32::MAX as u64, u32::MAX as u64], vec![255, 1])
+    );
+
+    // The program digest is the same in every mode, the statement digest is not.
+    let digests = |cell_mode| {
+        let config = MachineConfig {
+            cell_mode,
+            ..Default::default()
+        };
+        let machine = Machine::with_config(code.clone(), std::io::empty(), Vec::new(), config);
+        (machine.program_digest(), machine.statement_digest())
+    };
+    let (field_program, field_statement) = digests(CellMode::Field);
+    let (u8_program, u8_statement) = digests(CellMode::U8);
+    assert_eq!(field_program, u8_program);
+    assert_ne!(field_statement, u8_statement);
+}
+
+#[test]
+fn test_execute_eof_policies() {
+    use crate::compiler::Compiler;
+
+    let code = Compiler::new("+>+,.>,.<<,.".to_string()).compile().unwrap();
+    let run = |eof, cell_mode| {
+        let config = MachineConfig {
+            eof,
+            cell_mode,
+            ..Default::default()
+        };
+        let mut machine = Machine::with_config(code.clone(), &b"a"[..], Vec::new(), config);
+        let result = machine.execute();
+        let tape: Vec<u64> = machine.input_tape().iter().map(|value| value.0).collect();
+        (result, tape, machine.into_output())
+    };
+
+    let (result, tape, output) = run(EofPolicy::Zero, CellMode::Field);
+    result.unwrap();
+    assert_eq!((tape, output), (vec![97, 0, 0], vec![97, 0, 0]));
+    let (_, tape, output) = run(EofPolicy::MinusOne, CellMode::U8);
+    assert_eq!((tape, output), (vec![97, 255, 255], vec![97, 255, 255]));
+    let (_, tape, _) = run(EofPolicy::MinusOne, CellMode::Field);
+    assert_eq!(tape[1], (FieldElement::zero() - FieldElement::one()).0);
+    let (_, tape, output) = run(EofPolicy::Unchanged, CellMode::Field);
+    assert_eq!((tape, output), (vec![97, 0, 1], vec![97, 0, 1]));
+
+    let (result, tape, output) = run(EofPolicy::Error, CellMode::Field);
+    assert!(matches!(
+        result.unwrap_err().kind,
+        MachineErrorKind::EndOfInput
+    ));
+    assert_eq!((tape, output), (vec![97], vec![97]));
 }
@@ -1,31 +1,304 @@
 use std::{
-    fs,
-    io::{stdin, stdout},
+    env,
+    fs::File,
+    io::{stdin, stdout, Read},
 };
 
-use compiler::Compiler;
-use machine::Machine;
+use compiler::{Compiler, CompilerConfig, SourceMap};
+use crypto::{field::FieldElement, sha256::to_hex};
+use dialect::Dialect;
+use machine::{strip_breakpoints, CellMode, EofPolicy, Machine, MachineConfig};
+use preprocessor::{Origin, Preprocessed};
 
+pub mod bytecode;
+pub mod cfg;
 pub mod compiler;
 pub mod crypto;
+pub mod dialect;
+pub mod disassembler;
+pub mod formatter;
+pub mod generator;
 pub mod instruction;
+pub mod ir;
+pub mod lang;
+pub mod lint;
 pub mod machine;
+pub mod minimizer;
+pub mod optimizer;
+pub mod preprocessor;
 pub mod registers;
 
+/// Dialect of a source file, by extension: `.ook` for Ook! and `.blub` for Blub.
+fn dialect_of(target_file: &str) -> Option<Dialect> {
+    match target_file.rsplit_once('.')?.1 {
+        "ook" => Some(Dialect::ook()),
+        "blub" => Some(Dialect::blub()),
+        _ => None,
+    }
+}
+
+fn compile_file(
+    target_file: &str,
+    config: CompilerConfig,
+) -> (Vec<FieldElement>, SourceMap, Preprocessed) {
+    // Only `.bfm` macro sources are preprocessed, so `$` and `%` stay comments in `.bf`.
+    if target_file.ends_with(".bfm") {
+        let preprocessed = preprocessor::preprocess_file(target_file).unwrap_or_else(|err| {
+            eprintln!("❌ Preprocess error:\n{}", err);
+            std::process::exit(1);
+        });
+        let compiler = Compiler::with_config(preprocessed.source.clone(), config);
+        return finish_compile(target_file, compiler, preprocessed);
+    }
+    let source = std::fs::read_to_string(target_file)
+        .unwrap_or_else(|_| panic!("Failed to read file: {}", target_file));
+    let compiler = match dialect_of(target_file) {
+        Some(dialect) => dialect.compiler(&source, config),
+        None => Compiler::with_config(source.clone(), config),
+    };
+    finish_compile(
+        target_file,
+        compiler,
+        preprocessor::verbatim(&source, target_file),
+    )
+}
+
+fn finish_compile(
+    target_file: &str,
+    mut bf_compiler: Compiler,
+    preprocessed: Preprocessed,
+) -> (Vec<FieldElement>, SourceMap, Preprocessed) {
+    match bf_compiler.compile() {
+        Ok(ins) => (ins, bf_compiler.source_map(), preprocessed),
+        Err(err) => {
+            eprintln!("❌ Compile error:");
+            for error in err.errors {
+                match preprocessed.origin(error.position()) {
+                    Some(origin) => eprintln!("{}: {}", origin, error.message()),
+                    None => eprintln!("{}:{}", target_file, error),
+                }
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Original `file:line:col` of the instruction at `ip`, when the program was compiled from source.
+fn locate<'a>(
+    source_map: &SourceMap,
+    preprocessed: &'a Preprocessed,
+    ip: usize,
+) -> Option<&'a Origin> {
+    preprocessed.origin(source_map.get(ip)?)
+}
+
+/// Runner options followed by a value.
+const OPTIONS_WITH_VALUE: [&str; 3] = ["--input", "--cells", "--eof"];
+
+/// Value following the option `name`, e.g. the file of `--input <file>`.
+fn option_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    Some(
+        args.get(index + 1)
+            .unwrap_or_else(|| panic!("Missing value for {}", name)),
+    )
+}
+
 fn main() {
-    println!("0️⃣ Which brainfuck file you want to execute?");
-    let input = &mut String::new();
-    let stdin = stdin();
-    stdin.read_line(input).expect("Failed to read line");
-    let target_file = format!("examples/{}.bf", input.trim());
-    let code = fs::read_to_string(&target_file)
-        .unwrap_or_else(|_| panic!("Failed to read file: {}", target_file))
-        .replace(' ', "");
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let [command, source_file, output_file] = args.as_slice() {
+        if command == "compile" {
+            // bfzm compile <program.bf> <program.bfc>
+            let (ins, _, _) = compile_file(source_file, CompilerConfig::default());
+            bytecode::save(output_file, &ins)
+                .unwrap_or_else(|err| panic!("Failed to write {}: {}", output_file, err));
+            println!(
+                "Wrote {} ({} instructions, digest {})",
+                output_file,
+                ins.len(),
+                to_hex(&bytecode::program_digest(&ins))
+            );
+            return;
+        }
+        if command == "build" {
+            // bfzm build <program.bfl> <program.bf>
+            let source = std::fs::read_to_string(source_file)
+                .unwrap_or_else(|_| panic!("Failed to read file: {}", source_file));
+            let bf = lang::compile(&source).unwrap_or_else(|err| {
+                eprintln!("❌ {}:{}", source_file, err);
+                std::process::exit(1);
+            });
+            std::fs::write(output_file, bf)
+                .unwrap_or_else(|err| panic!("Failed to write {}: {}", output_file, err));
+            println!(
+                "Wrote {} (byte variables, run with --cells {})",
+                output_file,
+                lang::CELL_MODE
+            );
+            return;
+        }
+        if command == "minimize" {
+            // bfzm minimize <program.bf> <program.min.bf>
+            let source = std::fs::read_to_string(source_file)
+                .unwrap_or_else(|_| panic!("Failed to read file: {}", source_file));
+            let minimized = minimizer::minimize(&source).unwrap_or_else(|err| {
+                for error in err.errors {
+                    eprintln!("❌ {}:{}", source_file, error);
+                }
+                std::process::exit(1);
+            });
+            std::fs::write(output_file, &minimized)
+                .unwrap_or_else(|err| panic!("Failed to write {}: {}", output_file, err));
+            println!("Wrote {} ({} commands)", output_file, minimized.len());
+            return;
+        }
+    }
+    if let Some((command, files)) = args.split_first() {
+        if command == "fmt" {
+            // bfzm fmt [--check] <program.bf>...
+            let check = files.first().is_some_and(|arg| arg == "--check");
+            let files = if check { &files[1..] } else { files };
+            if let Some(file) = files.iter().find(|file| !formatter::is_formattable(file)) {
+                eprintln!("❌ {}: only plain .bf sources can be formatted", file);
+                std::process::exit(1);
+            }
+            let mut unformatted = false;
+            for file in files {
+                let source = std::fs::read_to_string(file)
+                    .unwrap_or_else(|_| panic!("Failed to read file: {}", file));
+                let formatted = formatter::format(&source).unwrap_or_else(|err| {
+                    for error in err.errors {
+                        eprintln!("❌ {}:{}", file, error);
+                    }
+                    std::process::exit(1);
+                });
+                if formatted == source {
+                    continue;
+                }
+                if check {
+                    println!("Not formatted: {}", file);
+                    unformatted = true;
+                } else {
+                    std::fs::write(file, formatted)
+                        .unwrap_or_else(|err| panic!("Failed to write {}: {}", file, err));
+                    println!("Formatted {}", file);
+                }
+            }
+            std::process::exit(if unformatted { 1 } else { 0 });
+        }
+        if command == "generate" {
+            // bfzm generate <text> <program.bf> [trace length]
+            let (text, output_file) = match files {
+                [text, output_file, ..] => (text, output_file),
+                _ => panic!("Usage: generate <text> <program.bf> [trace length]"),
+            };
+            let program = match files.get(2) {
+                Some(trace_length) => {
+                    let trace_length = trace_length
+                        .parse()
+                        .unwrap_or_else(|_| panic!("Invalid trace length: {}", trace_length));
+                    generator::generate_with_trace_length(text.as_bytes(), trace_length)
+                        .unwrap_or_else(|err| panic!("{}", err))
+                }
+                None => generator::generate(text.as_bytes()),
+            };
+            std::fs::write(output_file, program)
+                .unwrap_or_else(|err| panic!("Failed to write {}: {}", output_file, err));
+            println!("Wrote {}", output_file);
+            return;
+        }
+        if command == "cfg" {
+            // bfzm cfg <program.bf> <graph.dot> [--counts]
+            let (source_file, output_file) = match files {
+                [source_file, output_file, ..] => (source_file, output_file),
+                _ => panic!("Usage: cfg <program.bf> <graph.dot> [--counts]"),
+            };
+            let (ins, source_map, _) = compile_file(source_file, CompilerConfig::default());
+            let graph = cfg::Cfg::build(&ins, Some(&source_map))
+                .unwrap_or_else(|err| panic!("Failed to build graph: {}", err));
+            let counts = if files.get(2).is_some_and(|arg| arg == "--counts") {
+                let mut bf_vm = Machine::new(ins, stdin(), stdout());
+                if let Err(err) = bf_vm.execute() {
+                    eprintln!("⚠️ Counting a partial run: {}", err);
+                }
+                Some(graph.execution_counts(&bf_vm.get_trace()))
+            } else {
+                None
+            };
+            std::fs::write(output_file, graph.to_dot(counts.as_deref()))
+                .unwrap_or_else(|err| panic!("Failed to write {}: {}", output_file, err));
+            println!("Wrote {} ({} blocks)", output_file, graph.blocks.len());
+            return;
+        }
+    }
+
+    // bfzm [program] [--input <file>] [--cells <field|u8|u16|u32>]
+    //      [--eof <zero|minus-one|unchanged|error>] [--debug] [--extended]
+    // Without a program name it is asked for on stdin.
+    let input_file = option_value(&args, "--input");
+    let cell_mode = option_value(&args, "--cells").map_or(CellMode::Field, |mode| {
+        mode.parse()
+            .unwrap_or_else(|_| panic!("Invalid cell mode: {}", mode))
+    });
+    let eof = option_value(&args, "--eof").map_or(EofPolicy::Error, |eof| {
+        eof.parse()
+            .unwrap_or_else(|_| panic!("Invalid EOF policy: {}", eof))
+    });
+    let program = args
+        .iter()
+        .enumerate()
+        .find(|(index, arg)| {
+            !arg.starts_with("--")
+                && (*index == 0 || !OPTIONS_WITH_VALUE.contains(&args[index - 1].as_str()))
+        })
+        .map(|(_, arg)| arg.clone());
+    let input = match program {
+        Some(program) => program,
+        None => {
+            println!("0️⃣ Which brainfuck file you want to execute?");
+            let mut input = String::new();
+            stdin().read_line(&mut input).expect("Failed to read line");
+            input
+        }
+    };
+    let input = input.trim();
+    let target_file = if [".bfc", ".bfm", ".ook", ".blub"]
+        .iter()
+        .any(|extension| input.ends_with(extension))
+    {
+        format!("examples/{}", input)
+    } else {
+        format!("examples/{}.bf", input)
+    };
     println!("Selected program: {}", target_file);
     println!("\n======================== ");
-    println!("1️⃣ Compiling...");
-    let mut bf_compiler = Compiler::new(code);
-    let ins = bf_compiler.compile();
+    let (ins, source_map, preprocessed) = if target_file.ends_with(".bfc") {
+        println!("1️⃣ Loading bytecode...");
+        let ins = bytecode::load(&target_file)
+            .unwrap_or_else(|err| panic!("Failed to load {}: {}", target_file, err));
+        (ins, SourceMap::default(), Preprocessed::default())
+    } else {
+        println!("1️⃣ Compiling...");
+        // `--debug` keeps `#` breakpoints, which dump the machine state to stderr, and
+        // `--extended` enables the `(`, `)` and `:` procedure commands.
+        let config = CompilerConfig {
+            debug: args.iter().any(|arg| arg == "--debug"),
+            extended: args.iter().any(|arg| arg == "--extended"),
+            ..Default::default()
+        };
+        compile_file(&target_file, config)
+    };
+    match lint::lint(&ins, Some(&source_map), cell_mode) {
+        Ok(diagnostics) => {
+            for diagnostic in diagnostics {
+                match locate(&source_map, &preprocessed, diagnostic.ip) {
+                    Some(origin) => println!("⚠️ {}: warning: {}", origin, diagnostic.message),
+                    None => println!("⚠️ {}", diagnostic),
+                }
+            }
+        }
+        Err(err) => println!("⚠️ Lint skipped: {}", err),
+    }
     println!("🔥 Instructions:\n ");
     print!("[");
     for (index, ins) in ins.iter().enumerate() {
@@ -35,16 +308,76 @@ fn main() {
         print!("{}", ins);
     }
     println!("]");
+    // Breakpoints are not part of the program, so `--debug` builds share the digest.
+    println!(
+        "🔑 Program digest: {}",
+        to_hex(&bytecode::program_digest(&strip_breakpoints(&ins).code))
+    );
     println!("\n======================== ");
     println!("2️⃣ Executing program...");
-    let stdout = stdout();
-    let mut bf_vm = Machine::new(ins, stdin, stdout);
-    println!("input: ");
-    bf_vm.execute().unwrap();
+    let input: Box<dyn Read> = match input_file {
+        Some(input_file) => Box::new(
+            File::open(input_file)
+                .unwrap_or_else(|err| panic!("Failed to open {}: {}", input_file, err)),
+        ),
+        None => {
+            println!("input: ");
+            Box::new(stdin())
+        }
+    };
+    let config = MachineConfig {
+        cell_mode,
+        eof,
+        ..Default::default()
+    };
+    let mut bf_vm = Machine::with_config(ins, input, stdout(), config);
+    let result = bf_vm.execute();
     println!("\n ");
+    if let Err(err) = &result {
+        let address = bf_vm.code_address(err.ip);
+        match address.and_then(|address| locate(&source_map, &preprocessed, address)) {
+            Some(origin) => println!(
+                "❌ {}: runtime error: {} at clk {}, ip {}",
+                origin, err.kind, err.clk, err.ip
+            ),
+            None => println!("❌ Runtime error: {}", err),
+        }
+    }
     let traces = bf_vm.get_trace();
-    println!("🔥 Full execution trace:\n ");
+    println!(
+        "🔥 {} execution trace of program {} ({} cells, statement {}):",
+        if result.is_ok() { "Full" } else { "Partial" },
+        to_hex(&bf_vm.program_digest()),
+        bf_vm.config().cell_mode,
+        to_hex(&bf_vm.statement_digest())
+    );
+    let config = bf_vm.config();
+    println!(
+        "📏 Tape: {} cells allocated ({} at start, limit {}), max mp {}",
+        bf_vm.tape_size(),
+        config.tape_size,
+        if config.grow_tape {
+            config.max_tape_size
+        } else {
+            config.tape_size
+        },
+        bf_vm.max_mp()
+    );
+    // Every value `,` stored, which the trace's reads are checked against.
+    let input_tape: Vec<String> = bf_vm
+        .input_tape()
+        .iter()
+        .map(|value| value.to_string())
+        .collect();
+    println!("📥 Input tape: [{}]\n ", input_tape.join(", "));
     for trace in traces {
-        println!("{:?}", trace);
+        let address = bf_vm.code_address(trace.ip.to_usize());
+        match address.and_then(|address| locate(&source_map, &preprocessed, address)) {
+            Some(origin) => println!("{:?} @ {}", trace, origin),
+            None => println!("{:?}", trace),
+        }
+    }
+    if result.is_err() {
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,270 @@
+//! Shortens Brainfuck programs without changing what they print or read, or whether
+//! they fail. Every executed instruction is a row in the trace, so shorter programs are
+//! cheaper to prove.
+//!
+//! Rewrites, applied until none of them changes the program:
+//!
+//! - adjacent `+-`, `-+`, `><` and `<>` cancel out, except a `<>` that may start on
+//!   cell 0, where `<` fails;
+//! - loops where the current cell is provably zero are removed, i.e. loops before the
+//!   tape is first written and loops right after another loop;
+//! - trailing `+ - < >` after the last output, input or loop are dropped, except a `<`
+//!   that may start on cell 0.
+//!
+//! Whether `<` is safe comes from a lower bound on the memory pointer, which is only
+//! known in straight-line code. Moves past the end of the tape are assumed not to
+//! happen, since where the tape ends depends on `MachineConfig`. Loops are never
+//! removed from the end of a program, since whether they terminate is observable.
+//! Comments are dropped.
+
+use crate::compiler::{CompileError, Compiler};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Command(char),
+    Loop(Vec<Node>),
+}
+
+fn parse(source: &str) -> Vec<Node> {
+    let mut stack = vec![];
+    let mut nodes = vec![];
+    for symbol in source.chars() {
+        match symbol {
+            '[' => stack.push(std::mem::take(&mut nodes)),
+            ']' => {
+                let body = std::mem::replace(&mut nodes, stack.pop().unwrap());
+                nodes.push(Node::Loop(body));
+            }
+            '+' | '-' | '<' | '>' | '.' | ',' => nodes.push(Node::Command(symbol)),
+            _ => (),
+        }
+    }
+    nodes
+}
+
+fn render(nodes: &[Node], out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Command(symbol) => out.push(*symbol),
+            Node::Loop(body) => {
+                out.push('[');
+                render(body, out);
+                out.push(']');
+            }
+        }
+    }
+}
+
+fn inverse(symbol: char) -> Option<char> {
+    match symbol {
+        '+' => Some('-'),
+        '-' => Some('+'),
+        '<' => Some('>'),
+        '>' => Some('<'),
+        _ => None,
+    }
+}
+
+/// Lower bound on the memory pointer after `node`, given the bound `low` before it.
+fn next_low(low: usize, node: &Node) -> usize {
+    match node {
+        Node::Command('>') => low + 1,
+        Node::Command('<') => low.saturating_sub(1),
+        Node::Command(_) => low,
+        // Loops may move the pointer anywhere.
+        Node::Loop(_) => 0,
+    }
+}
+
+/// Lower bound on the memory pointer before each of `nodes`, starting from cell 0.
+fn lows(nodes: &[Node]) -> Vec<usize> {
+    let mut low = 0;
+    nodes
+        .iter()
+        .map(|node| {
+            let before = low;
+            low = next_low(low, node);
+            before
+        })
+        .collect()
+}
+
+/// Removes adjacent pairs that undo each other. `low` bounds the memory pointer from
+/// below on entry, so `<>` only cancels where its `<` cannot fail.
+fn cancel_pairs(nodes: Vec<Node>, mut low: usize) -> Vec<Node> {
+    let mut out: Vec<Node> = vec![];
+    // Bound before each node of `out`, to restore it when a pair cancels.
+    let mut out_lows = vec![];
+    for node in nodes {
+        if let (Node::Command(symbol), Some(Node::Command(last))) = (&node, out.last()) {
+            let before = *out_lows.last().unwrap();
+            if inverse(*last) == Some(*symbol) && (*last != '<' || before > 0) {
+                out.pop();
+                low = out_lows.pop().unwrap();
+                continue;
+            }
+        }
+        out_lows.push(low);
+        low = next_low(low, &node);
+        match node {
+            Node::Loop(body) => out.push(Node::Loop(cancel_pairs(body, 0))),
+            node => out.push(node),
+        }
+    }
+    out
+}
+
+/// Removes loops entered while the current cell is known to be zero. `tape_zero`
+/// means no cell has been written yet.
+fn remove_dead_loops(nodes: Vec<Node>, mut tape_zero: bool) -> Vec<Node> {
+    let mut cell_zero = tape_zero;
+    let mut out = vec![];
+    for node in nodes {
+        match node {
+            Node::Command(symbol) => {
+                match symbol {
+                    '+' | '-' | ',' => {
+                        cell_zero = false;
+                        tape_zero = false;
+                    }
+                    '<' | '>' => cell_zero = tape_zero,
+                    _ => (),
+                }
+                out.push(node);
+            }
+            Node::Loop(_) if cell_zero => (),
+            Node::Loop(body) => {
+                // The body only runs when the current cell is nonzero.
+                out.push(Node::Loop(remove_dead_loops(body, false)));
+                cell_zero = true;
+                tape_zero = false;
+            }
+        }
+    }
+    out
+}
+
+/// Drops trailing cell updates and pointer moves that nothing can observe, stopping at
+/// a `<` that may fail on cell 0.
+fn drop_unobservable_tail(mut nodes: Vec<Node>) -> Vec<Node> {
+    let lows = lows(&nodes);
+    while let Some(Node::Command(symbol @ ('+' | '-' | '<' | '>'))) = nodes.last() {
+        if *symbol == '<' && lows[nodes.len() - 1] == 0 {
+            break;
+        }
+        nodes.pop();
+    }
+    nodes
+}
+
+/// Minimized program with only command characters. Fails on the same bracket errors
+/// as `Compiler`.
+pub fn minimize(source: &str) -> Result<String, CompileError> {
+    Compiler::new(source.to_string()).compile()?;
+
+    let mut nodes = parse(source);
+    loop {
+        let next = drop_unobservable_tail(remove_dead_loops(cancel_pairs(nodes.clone(), 0), true));
+        if next == nodes {
+            break;
+        }
+        nodes = next;
+    }
+    let mut out = String::new();
+    render(&nodes, &mut out);
+    Ok(out)
+}
+
+/// Runs `source` on `Machine`, returning its output and trace length.
+#[cfg(test)]
+fn run(source: &str, input: &[u8]) -> (Vec<u8>, usize) {
+    let run = crate::machine::run_source(source, input);
+    run.result.unwrap();
+    (run.output, run.trace.len())
+}
+
+/// Checks `minimize(source) == expected`, and that both run to the same output with
+/// the minimized trace strictly shorter.
+#[cfg(test)]
+fn assert_minimizes(source: &str, expected: &str, input: &[u8]) {
+    let minimized = minimize(source).unwrap();
+    assert_eq!(minimized, expected, "{}", source);
+    let (output, trace_length) = run(source, input);
+    let (minimized_output, minimized_trace_length) = run(&minimized, input);
+    assert_eq!(minimized_output, output, "{}", source);
+    assert!(minimized_trace_length < trace_length, "{}", source);
+}
+
+#[test]
+fn test_minimize_cancels_pairs() {
+    assert_minimizes("+++-->+<><.", "+>+<.", b"");
+    // Cancelling can expose new pairs.
+    assert_minimizes(",+>+-<-.", ",.", b"a");
+    // Pairs do not cancel across loop boundaries.
+    assert_minimizes("+++[->+<]+-.>.", "+++[->+<].>.", b"");
+}
+
+#[test]
+fn test_minimize_removes_dead_loops() {
+    assert_minimizes("[comment loop .,]>[-]++.", ">++.", b"");
+    assert_minimizes("++[->+<][>.<]>.", "++[->+<]>.", b"");
+    assert_minimizes(",[[-][.]]>.", ",[[-]]>.", b"\x01");
+}
+
+#[test]
+fn test_minimize_drops_unobservable_tail() {
+    assert_minimizes("++.>+++<><", "++.", b"");
+    // Loops at the end stay: they might not terminate.
+    assert_eq!(minimize("+.[-]").unwrap(), "+.[-]");
+    assert_eq!(minimize("+.,+").unwrap(), "+.,");
+}
+
+#[test]
+fn test_minimize_keeps_runtime_errors() {
+    use crate::machine::{run_source, MachineErrorKind};
+
+    // Each of these moves left of cell 0, so the minimized program has to as well.
+    for source in ["<>+.", "+.<", "+.<><", ">+<<>.", "+[-<+>]>."] {
+        let minimized = minimize(source).unwrap();
+        let original = run_source(source, b"");
+        let minimized_run = run_source(&minimized, b"");
+        assert!(original.result.is_err(), "{}", source);
+        let kinds = (
+            original.result.unwrap_err().kind,
+            minimized_run.result.map_err(|err| err.kind),
+        );
+        assert!(
+            matches!(
+                kinds,
+                (
+                    MachineErrorKind::PointerUnderflow,
+                    Err(MachineErrorKind::PointerUnderflow)
+                )
+            ),
+            "{} -> {}",
+            source,
+            minimized
+        );
+        assert_eq!(minimized_run.output, original.output, "{}", source);
+    }
+    // Away from cell 0 the same pairs still go.
+    assert_minimizes(">+<>.", ">+.", b"");
+    assert_minimizes(">+.<", ">+.", b"");
+}
+
+#[test]
+fn test_minimize_preserves_generated_programs() {
+    let sources = [
+        include_str!("../examples/1.bf").to_string(),
+        crate::generator::generate_with_trace_length(b"Hi!", 600).unwrap(),
+        crate::lang::compile("x = 3; while x { print 'a' + x; x -= 1; }").unwrap() + "+-><",
+    ];
+    for source in sources {
+        let minimized = minimize(&source).unwrap();
+        let (output, trace_length) = run(&source, b"");
+        let (minimized_output, minimized_trace_length) = run(&minimized, b"");
+        assert_eq!(minimized_output, output);
+        assert!(minimized_trace_length <= trace_length);
+        assert_eq!(minimize(&minimized).unwrap(), minimized);
+    }
+}
@@ -0,0 +1,345 @@
+//! Idiom recognition on top of the run-length IR. Loops that only shuffle values
+//! between cells are replaced with fused operations so that `execute` can run them
+//! in a single step instead of once per iteration.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Read, Write},
+};
+
+use crate::{
+    crypto::field::FieldElement,
+    instruction::{Instruction, InstructionType},
+    ir::IrError,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// Add to the current cell, `-` runs are folded in as negated values.
+    Add(FieldElement),
+    /// Move the memory pointer.
+    Move(isize),
+    PutChar,
+    ReadChar,
+    /// `[-]` or `[+]`: set the current cell to zero.
+    Clear,
+    /// Body of `[->+<]` or `[->+++>++<<]`: add the current cell times `factor` to the
+    /// cell at `offset`. Always followed by `Clear`. Does nothing on a zero cell, where
+    /// the loop would have been skipped.
+    MulAdd {
+        offset: isize,
+        factor: FieldElement,
+    },
+    /// `[>]` or `[<<]`: move by `step` until the current cell is zero.
+    Scan(isize),
+    /// Loop that matched no idiom, with the index of its matching op.
+    JumpIfZero(usize),
+    JumpIfNotZero(usize),
+    /// `(`: define the procedure numbered by the current cell, skipping to its `Return`.
+    DefineProcedure(usize),
+    Call,
+    Return,
+}
+
+fn push_add(ops: &mut Vec<Op>, value: FieldElement) {
+    if let Some(Op::Add(last)) = ops.last_mut() {
+        *last += value;
+        return;
+    }
+    ops.push(Op::Add(value));
+}
+
+fn push_move(ops: &mut Vec<Op>, step: isize) {
+    if let Some(Op::Move(last)) = ops.last_mut() {
+        *last += step;
+        return;
+    }
+    ops.push(Op::Move(step));
+}
+
+/// Fused replacement for a loop body made only of `Add` and `Move`, if it is one of
+/// the clear, move/multiply or scan idioms.
+fn recognize(body: &[Op]) -> Option<Vec<Op>> {
+    let mut deltas = BTreeMap::new();
+    let mut pos = 0isize;
+    for op in body {
+        match op {
+            Op::Add(value) => *deltas.entry(pos).or_insert(FieldElement::zero()) += *value,
+            Op::Move(step) => pos += step,
+            _ => return None,
+        }
+    }
+
+    if pos != 0 {
+        let only_moves = deltas.values().all(|delta| *delta == FieldElement::zero());
+        return only_moves.then(|| vec![Op::Scan(pos)]);
+    }
+
+    // The loop runs `v` times when the current cell steps by -1, and `-v` times
+    // (mod p) when it steps by +1. Any other step depends on the cell width.
+    let scale = match deltas.get(&0) {
+        Some(delta) if *delta == -FieldElement::one() => FieldElement::one(),
+        Some(delta) if *delta == FieldElement::one() => -FieldElement::one(),
+        _ => return None,
+    };
+    let mut fused: Vec<Op> = deltas
+        .into_iter()
+        .filter(|(offset, delta)| *offset != 0 && *delta != FieldElement::zero())
+        .map(|(offset, delta)| Op::MulAdd {
+            offset,
+            factor: delta * scale,
+        })
+        .collect();
+    fused.push(Op::Clear);
+    Some(fused)
+}
+
+pub fn optimize(program: &[Instruction]) -> Result<Vec<Op>, IrError> {
+    let mut ops = vec![];
+    let mut loop_stack = vec![];
+    for (index, ins) in program.iter().enumerate() {
+        let count = ins.argument as usize;
+        match ins.ins_type {
+            InstructionType::Plus => push_add(&mut ops, FieldElement::from(count)),
+            InstructionType::Minus => push_add(&mut ops, -FieldElement::from(count)),
+            InstructionType::Right => push_move(&mut ops, count as isize),
+            InstructionType::Left => push_move(&mut ops, -(count as isize)),
+            InstructionType::PutChar => ops.push(Op::PutChar),
+            InstructionType::ReadChar => ops.push(Op::ReadChar),
+            InstructionType::JumpIfZero => {
+                loop_stack.push((ops.len(), ins.ins_type));
+                ops.push(Op::JumpIfZero(0));
+            }
+            InstructionType::ProcedureStart => {
+                loop_stack.push((ops.len(), ins.ins_type));
+                ops.push(Op::DefineProcedure(0));
+            }
+            InstructionType::ProcedureEnd => match loop_stack.pop() {
+                Some((start, InstructionType::ProcedureStart)) => {
+                    ops[start] = Op::DefineProcedure(ops.len());
+                    ops.push(Op::Return);
+                }
+                _ => return Err(IrError::UnmatchedLoop { index }),
+            },
+            InstructionType::Call => ops.push(Op::Call),
+            InstructionType::JumpIfNotZero => {
+                let start = match loop_stack.pop() {
+                    Some((start, InstructionType::JumpIfZero)) => start,
+                    _ => return Err(IrError::UnmatchedLoop { index }),
+                };
+                if let Some(fused) = recognize(&ops[start + 1..]) {
+                    ops.truncate(start);
+                    ops.extend(fused);
+                } else {
+                    ops[start] = Op::JumpIfZero(ops.len());
+                    ops.push(Op::JumpIfNotZero(start));
+                }
+            }
+            InstructionType::Breakpoint => (),
+        }
+    }
+    if !loop_stack.is_empty() {
+        return Err(IrError::UnmatchedLoop {
+            index: program.len(),
+        });
+    }
+    Ok(ops)
+}
+
+/// Resolves `mp + offset`, growing the tape on demand.
+fn address(
+    ram: &mut Vec<FieldElement>,
+    mp: usize,
+    offset: isize,
+    index: usize,
+) -> Result<usize, IrError> {
+    let target = mp
+        .checked_add_signed(offset)
+        .ok_or(IrError::PointerUnderflow { index })?;
+    if target >= ram.len() {
+        ram.resize(target + 1, FieldElement::zero());
+    }
+    Ok(target)
+}
+
+/// Runs optimized ops on field element cells, like `ir::execute`.
+pub fn execute<R: Read, W: Write>(
+    ops: &[Op],
+    input: &mut R,
+    output: &mut W,
+) -> Result<(), IrError> {
+    let mut procedures = HashMap::new();
+    let mut call_stack = vec![];
+    let mut ram = vec![FieldElement::zero(); 100];
+    let mut mp = 0usize;
+    let mut pc = 0;
+    while pc < ops.len() {
+        match &ops[pc] {
+            Op::Add(value) => ram[mp] += *value,
+            Op::Move(step) => mp = address(&mut ram, mp, *step, pc)?,
+            Op::PutChar => output.write_all(&[ram[mp].to_usize() as u8])?,
+            Op::ReadChar => {
+                let mut buf = [0; 1];
+                input.read_exact(&mut buf)?;
+                ram[mp] = FieldElement::from(buf[0] as u64);
+            }
+            Op::Clear => ram[mp] = FieldElement::zero(),
+            Op::MulAdd { offset, factor } => {
+                let value = ram[mp];
+                if value != FieldElement::zero() {
+                    let target = address(&mut ram, mp, *offset, pc)?;
+                    ram[target] += value * *factor;
+                }
+            }
+            Op::Scan(step) => {
+                while ram[mp] != FieldElement::zero() {
+                    mp = address(&mut ram, mp, *step, pc)?;
+                }
+            }
+            Op::JumpIfZero(end) => {
+                if ram[mp] == FieldElement::zero() {
+                    pc = *end;
+                }
+            }
+            Op::JumpIfNotZero(start) => {
+                if ram[mp] != FieldElement::zero() {
+                    pc = *start;
+                }
+            }
+            Op::DefineProcedure(end) => {
+                procedures.insert(ram[mp], pc + 1);
+                pc = *end;
+            }
+            Op::Call => {
+                let body = procedures
+                    .get(&ram[mp])
+                    .ok_or(IrError::UndefinedProcedure { index: pc })?;
+                call_stack.push(pc);
+                pc = body - 1;
+            }
+            Op::Return => {
+                pc = call_stack
+                    .pop()
+                    .expect("`Return` is only reached from a call");
+            }
+        }
+        pc += 1;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn optimize_source(code: &str) -> Vec<Op> {
+    let code = crate::compiler::Compiler::new(code.to_string())
+        .compile()
+        .unwrap();
+    optimize(&crate::ir::lower(&code).unwrap()).unwrap()
+}
+
+#[test]
+fn test_optimize_idioms() {
+    assert_eq!(optimize_source("[-]"), vec![Op::Clear]);
+    assert_eq!(optimize_source("[+]"), vec![Op::Clear]);
+    assert_eq!(
+        optimize_source("[->+<]"),
+        vec![
+            Op::MulAdd {
+                offset: 1,
+                factor: FieldElement::one()
+            },
+            Op::Clear
+        ]
+    );
+    assert_eq!(
+        optimize_source("[->+++>++<<]"),
+        vec![
+            Op::MulAdd {
+                offset: 1,
+                factor: FieldElement::from(3u64)
+            },
+            Op::MulAdd {
+                offset: 2,
+                factor: FieldElement::from(2u64)
+            },
+            Op::Clear
+        ]
+    );
+    assert_eq!(optimize_source("[>]"), vec![Op::Scan(1)]);
+    assert_eq!(optimize_source("[<<]"), vec![Op::Scan(-2)]);
+}
+
+#[test]
+fn test_optimize_keeps_other_loops() {
+    assert_eq!(
+        optimize_source("[]"),
+        vec![Op::JumpIfZero(1), Op::JumpIfNotZero(0)]
+    );
+    assert_eq!(
+        optimize_source("[--]"),
+        vec![
+            Op::JumpIfZero(2),
+            Op::Add(-FieldElement::from(2u64)),
+            Op::JumpIfNotZero(0)
+        ]
+    );
+    assert_eq!(
+        optimize_source("[>+]"),
+        vec![
+            Op::JumpIfZero(3),
+            Op::Move(1),
+            Op::Add(FieldElement::one()),
+            Op::JumpIfNotZero(0)
+        ]
+    );
+}
+
+#[test]
+fn test_optimize_matches_machine() {
+    use crate::{
+        compiler::{Compiler, CompilerConfig},
+        machine::{run_code, MachineErrorKind},
+    };
+
+    let programs: [(&str, &[u8]); 11] = [
+        (include_str!("../examples/1.bf"), b""),
+        (",>,<[->+<]>.", &[30, 40]),
+        (",[->+++>++<<]>.>.", &[7]),
+        ("+++++[>+++++++++++++<-]>[>+>+<<-]>.>.", b""),
+        ("+>+>+>+>>>,[<]<+.[>]>.", b"x"),
+        (",[.[-],]", b"bfzm"),
+        ("+++[>+++[>+++<-]<-]>>.[-]+.--.", b""),
+        // Procedure 1 doubles the next cell, procedure 2 calls procedure 1.
+        ("+(>[->++<]>[-<+>]<<)+(-:+)>+++<::>.", b""),
+        (",(>+<):::>.", &[0]),
+        // A skipped move loop at cell 0 never moves left, an entered one underflows.
+        ("[-<+>]+.", b""),
+        ("+.[-<+>]", b""),
+    ];
+    let config = CompilerConfig {
+        extended: true,
+        ..Default::default()
+    };
+    for (source, input) in programs {
+        let code = Compiler::with_config(source.to_string(), config.clone())
+            .compile()
+            .unwrap();
+        let ops = optimize(&crate::ir::lower(&code).unwrap()).unwrap();
+
+        let mut fast_output = vec![];
+        let mut fast_input = input;
+        let fast_result = execute(&ops, &mut fast_input, &mut fast_output);
+
+        let traced = run_code(code, input);
+        assert_eq!(fast_output, traced.output, "{}", source);
+        // Programs that read until end of input stop the same way in both paths.
+        let same_stop = match (&fast_result, &traced.result) {
+            (Ok(()), Ok(())) => true,
+            (Err(IrError::Io(_)), Err(err)) => matches!(err.kind, MachineErrorKind::EndOfInput),
+            (Err(IrError::PointerUnderflow { .. }), Err(err)) => {
+                matches!(err.kind, MachineErrorKind::PointerUnderflow)
+            }
+            _ => false,
+        };
+        assert!(same_stop, "{}: {:?}", source, (fast_result, traced.result));
+    }
+}
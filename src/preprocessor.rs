@@ -0,0 +1,447 @@
+//! Expands macro source into plain Brainfuck for `Compiler`:
+//!
+//! - `+*65` repeats a command, `$name*3` repeats a macro invocation.
+//! - `%define name body` on its own line defines a macro, `$name` expands it.
+//! - `%include "path"` on its own line inlines a file, relative to the including file.
+//!
+//! Everything else is copied as is. Every output character remembers the file, line and
+//! column it came from. Since `$`, `%` and `*` mean something here, only macro sources
+//! (`.bfm` files) are preprocessed; plain `.bf` files go to `Compiler` unchanged.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    str::FromStr,
+};
+
+use crate::{compiler::SourcePosition, instruction::InstructionType};
+
+/// Where a character of preprocessed output was written in the original sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub file: Rc<str>,
+    pub position: SourcePosition,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file, self.position)
+    }
+}
+
+#[derive(Debug)]
+pub enum PreprocessError {
+    UndefinedMacro {
+        name: String,
+        origin: Origin,
+    },
+    /// Macro that expands into itself.
+    RecursiveMacro {
+        name: String,
+        origin: Origin,
+    },
+    /// Directive line that is neither a valid `%define` nor `%include`.
+    InvalidDirective {
+        origin: Origin,
+    },
+    /// Repeat count or macro expansion growing the output past `MAX_EXPANSION` characters.
+    ExpansionTooLarge {
+        origin: Origin,
+    },
+    /// File that includes itself, directly or through other files.
+    IncludeCycle {
+        path: PathBuf,
+        origin: Origin,
+    },
+    Io {
+        path: PathBuf,
+        origin: Option<Origin>,
+        err: std::io::Error,
+    },
+}
+
+impl Display for PreprocessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreprocessError::UndefinedMacro { name, origin } => {
+                write!(f, "{}: undefined macro ${}", origin, name)
+            }
+            PreprocessError::RecursiveMacro { name, origin } => {
+                write!(f, "{}: macro ${} expands into itself", origin, name)
+            }
+            PreprocessError::InvalidDirective { origin } => {
+                write!(f, "{}: invalid directive", origin)
+            }
+            PreprocessError::ExpansionTooLarge { origin } => {
+                write!(
+                    f,
+                    "{}: expansion is longer than {} characters",
+                    origin, MAX_EXPANSION
+                )
+            }
+            PreprocessError::IncludeCycle { path, origin } => {
+                write!(f, "{}: {} includes itself", origin, path.display())
+            }
+            PreprocessError::Io { path, origin, err } => match origin {
+                Some(origin) => write!(f, "{}: cannot read {}: {}", origin, path.display(), err),
+                None => write!(f, "cannot read {}: {}", path.display(), err),
+            },
+        }
+    }
+}
+
+impl Error for PreprocessError {}
+
+/// Plain Brainfuck source with the origin of each character.
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessed {
+    pub source: String,
+    origins: Vec<Vec<Origin>>,
+}
+
+impl Preprocessed {
+    /// Maps a position in `source`, as reported by `Compiler`, back to the original file.
+    pub fn origin(&self, pos: SourcePosition) -> Option<&Origin> {
+        self.origins
+            .get(pos.line.checked_sub(1)?)?
+            .get(pos.column.checked_sub(1)?)
+    }
+}
+
+type Text = Vec<(char, Origin)>;
+
+/// Most characters preprocessing may produce, which bounds repeat counts.
+const MAX_EXPANSION: usize = 1 << 20;
+
+fn is_command(symbol: char) -> bool {
+    InstructionType::from_str(&symbol.to_string()).is_ok()
+}
+
+#[derive(Default)]
+struct Preprocessor {
+    macros: HashMap<String, Text>,
+    includes: Vec<PathBuf>,
+}
+
+impl Preprocessor {
+    fn file(
+        &mut self,
+        path: &Path,
+        origin: Option<Origin>,
+        out: &mut Text,
+    ) -> Result<(), PreprocessError> {
+        let io_error = |err| PreprocessError::Io {
+            path: path.to_path_buf(),
+            origin: origin.clone(),
+            err,
+        };
+        let canonical = fs::canonicalize(path).map_err(io_error)?;
+        if self.includes.contains(&canonical) {
+            return Err(PreprocessError::IncludeCycle {
+                path: path.to_path_buf(),
+                origin: origin.unwrap(),
+            });
+        }
+        let source = fs::read_to_string(path).map_err(io_error)?;
+        self.includes.push(canonical);
+        self.source(&source, path, out)?;
+        self.includes.pop();
+        Ok(())
+    }
+
+    fn source(&mut self, source: &str, path: &Path, out: &mut Text) -> Result<(), PreprocessError> {
+        let file: Rc<str> = path.to_string_lossy().into();
+        for (line, text) in source.lines().enumerate() {
+            let chars: Text = text
+                .chars()
+                .enumerate()
+                .map(|(column, symbol)| {
+                    let position = SourcePosition {
+                        line: line + 1,
+                        column: column + 1,
+                    };
+                    (
+                        symbol,
+                        Origin {
+                            file: file.clone(),
+                            position,
+                        },
+                    )
+                })
+                .collect();
+            let start = chars.iter().position(|(symbol, _)| !symbol.is_whitespace());
+            match start {
+                Some(start) if chars[start].0 == '%' => {
+                    self.directive(&chars[start..], path, out)?
+                }
+                _ => self.expand(&chars, &mut vec![], out)?,
+            }
+            let position = SourcePosition {
+                line: line + 1,
+                column: text.chars().count() + 1,
+            };
+            out.push((
+                '\n',
+                Origin {
+                    file: file.clone(),
+                    position,
+                },
+            ));
+        }
+        Ok(())
+    }
+
+    fn directive(
+        &mut self,
+        chars: &[(char, Origin)],
+        path: &Path,
+        out: &mut Text,
+    ) -> Result<(), PreprocessError> {
+        let origin = chars[0].1.clone();
+        let text: String = chars.iter().map(|(symbol, _)| symbol).collect();
+        let invalid = || PreprocessError::InvalidDirective {
+            origin: origin.clone(),
+        };
+        if let Some(rest) = text.strip_prefix("%define") {
+            let name: String = rest
+                .trim_start()
+                .chars()
+                .take_while(|symbol| symbol.is_alphanumeric() || *symbol == '_')
+                .collect();
+            if name.is_empty() || !rest.starts_with(char::is_whitespace) {
+                return Err(invalid());
+            }
+            let name_start = chars.len() - rest.trim_start().chars().count();
+            let body: Text = chars[name_start + name.chars().count()..]
+                .iter()
+                .skip_while(|(symbol, _)| symbol.is_whitespace())
+                .cloned()
+                .collect();
+            self.macros.insert(name, body);
+            return Ok(());
+        }
+        if let Some(rest) = text.strip_prefix("%include") {
+            let include = rest
+                .trim()
+                .strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+                .ok_or_else(invalid)?;
+            let target = path.parent().unwrap_or(Path::new("")).join(include);
+            return self.file(&target, Some(origin), out);
+        }
+        Err(invalid())
+    }
+
+    /// Expands macro invocations and repeat counts in `chars` into `out`.
+    fn expand(
+        &self,
+        chars: &[(char, Origin)],
+        active: &mut Vec<String>,
+        out: &mut Text,
+    ) -> Result<(), PreprocessError> {
+        let mut i = 0;
+        while i < chars.len() {
+            let (symbol, origin) = &chars[i];
+            let mut item = vec![];
+            if *symbol == '$' {
+                let name: String = chars[i + 1..]
+                    .iter()
+                    .map(|(symbol, _)| *symbol)
+                    .take_while(|symbol| symbol.is_alphanumeric() || *symbol == '_')
+                    .collect();
+                if name.is_empty() {
+                    out.push(chars[i].clone());
+                    i += 1;
+                    continue;
+                }
+                let body =
+                    self.macros
+                        .get(&name)
+                        .ok_or_else(|| PreprocessError::UndefinedMacro {
+                            name: name.clone(),
+                            origin: origin.clone(),
+                        })?;
+                if active.contains(&name) {
+                    return Err(PreprocessError::RecursiveMacro {
+                        name,
+                        origin: origin.clone(),
+                    });
+                }
+                i += 1 + name.chars().count();
+                active.push(name);
+                self.expand(body, active, &mut item)?;
+                active.pop();
+            } else if is_command(*symbol) {
+                item.push(chars[i].clone());
+                i += 1;
+            } else {
+                out.push(chars[i].clone());
+                i += 1;
+                continue;
+            }
+
+            let mut count = 1;
+            if chars.get(i).map(|(symbol, _)| *symbol) == Some('*') {
+                let digits: String = chars[i + 1..]
+                    .iter()
+                    .map(|(symbol, _)| *symbol)
+                    .take_while(|symbol| symbol.is_ascii_digit())
+                    .collect();
+                if !digits.is_empty() {
+                    count = digits.parse().unwrap_or(usize::MAX);
+                    i += 1 + digits.len();
+                }
+            }
+            let length = item.len().checked_mul(count);
+            if length.is_none_or(|length| out.len() + length > MAX_EXPANSION) {
+                return Err(PreprocessError::ExpansionTooLarge {
+                    origin: origin.clone(),
+                });
+            }
+            for _ in 0..count {
+                out.extend(item.iter().cloned());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn finish(text: Text) -> Preprocessed {
+    let mut preprocessed = Preprocessed::default();
+    let mut line = vec![];
+    for (symbol, origin) in text {
+        preprocessed.source.push(symbol);
+        if symbol == '\n' {
+            preprocessed.origins.push(std::mem::take(&mut line));
+        } else {
+            line.push(origin);
+        }
+    }
+    preprocessed.origins.push(line);
+    preprocessed
+}
+
+pub fn preprocess_file<P: AsRef<Path>>(path: P) -> Result<Preprocessed, PreprocessError> {
+    let mut out = vec![];
+    Preprocessor::default().file(path.as_ref(), None, &mut out)?;
+    Ok(finish(out))
+}
+
+/// Source that is compiled without preprocessing, such as a plain `.bf` file or a
+/// dialect program, with every character mapped to itself.
+pub fn verbatim<P: AsRef<Path>>(source: &str, path: P) -> Preprocessed {
+    let file: Rc<str> = path.as_ref().to_string_lossy().into();
+    let mut text = vec![];
+    for (line, content) in source.lines().enumerate() {
+        for (column, symbol) in content.chars().chain(std::iter::once('\n')).enumerate() {
+            let position = SourcePosition {
+                line: line + 1,
+                column: column + 1,
+            };
+            let origin = Origin {
+                file: file.clone(),
+                position,
+            };
+            text.push((symbol, origin));
+        }
+    }
+    finish(text)
+}
+
+/// Preprocesses in-memory source. `%include` paths resolve relative to `path`.
+pub fn preprocess_str<P: AsRef<Path>>(
+    source: &str,
+    path: P,
+) -> Result<Preprocessed, PreprocessError> {
+    let mut out = vec![];
+    Preprocessor::default().source(source, path.as_ref(), &mut out)?;
+    Ok(finish(out))
+}
+
+#[test]
+fn test_preprocess_repeat_counts() {
+    let preprocessed = preprocess_str("+*5>-*3 x*2 *", "main.bfm").unwrap();
+    assert_eq!(preprocessed.source, "+++++>--- x*2 *\n");
+}
+
+#[test]
+fn test_preprocess_macros() {
+    let source = "%define clear [-]\n%define move2 [->>+<<]\n+*3$move2 >>$clear*2\n";
+    let preprocessed = preprocess_str(source, "main.bfm").unwrap();
+    assert_eq!(preprocessed.source, "\n\n+++[->>+<<] >>[-][-]\n");
+}
+
+#[test]
+fn test_preprocess_preserves_positions() {
+    use crate::compiler::{Compiler, SyntaxError};
+
+    let source = "%define open [+\n+*2$open\n";
+    let preprocessed = preprocess_str(source, "main.bfm").unwrap();
+    let err = Compiler::new(preprocessed.source.clone())
+        .compile()
+        .unwrap_err();
+    let [SyntaxError::UnmatchedOpen(pos)] = err.errors[..] else {
+        panic!("unexpected errors: {}", err);
+    };
+    // The stray '[' is reported where the macro body defines it.
+    assert_eq!(
+        preprocessed.origin(pos).unwrap().to_string(),
+        "main.bfm:1:14"
+    );
+    let plus = SourcePosition { line: 2, column: 2 };
+    assert_eq!(
+        preprocessed.origin(plus).unwrap().to_string(),
+        "main.bfm:2:1"
+    );
+}
+
+#[test]
+fn test_preprocess_errors() {
+    assert!(matches!(
+        preprocess_str("$missing", "main.bfm"),
+        Err(PreprocessError::UndefinedMacro { .. })
+    ));
+    assert!(matches!(
+        preprocess_str("%define a +$a\n$a", "main.bfm"),
+        Err(PreprocessError::RecursiveMacro { .. })
+    ));
+    assert!(matches!(
+        preprocess_str("%undef a", "main.bfm"),
+        Err(PreprocessError::InvalidDirective { .. })
+    ));
+    for source in [
+        "+*99999999999",
+        "+*99999999999999999999999",
+        "%define a +*1000\n$a*2000",
+    ] {
+        assert!(matches!(
+            preprocess_str(source, "main.bfm"),
+            Err(PreprocessError::ExpansionTooLarge { .. })
+        ));
+    }
+    assert!(matches!(
+        preprocess_str("%include \"does-not-exist.bfm\"", "main.bfm"),
+        Err(PreprocessError::Io { .. })
+    ));
+}
+
+#[test]
+fn test_preprocess_include() {
+    let dir = std::env::temp_dir().join(format!("bfzm-preprocess-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("lib.bfm"), "%define clear [-]\n").unwrap();
+    fs::write(dir.join("main.bfm"), "%include \"lib.bfm\"\n+*2$clear\n").unwrap();
+    fs::write(dir.join("loop.bfm"), "%include \"loop.bfm\"\n").unwrap();
+
+    let preprocessed = preprocess_file(dir.join("main.bfm")).unwrap();
+    assert_eq!(preprocessed.source, "\n\n++[-]\n");
+    assert!(matches!(
+        preprocess_file(dir.join("loop.bfm")),
+        Err(PreprocessError::IncludeCycle { .. })
+    ));
+    fs::remove_dir_all(&dir).unwrap();
+}
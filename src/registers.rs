@@ -1,6 +1,6 @@
 use crate::crypto::field::FieldElement;
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct Registers {
     /// cycle
     pub clk: FieldElement,
@@ -16,6 +16,17 @@ pub struct Registers {
     pub mv: FieldElement,
     /// memory value inverse
     pub mvi: FieldElement,
+    /// call stack, only for programs using procedures
+    pub call: Option<CallRegisters>,
+}
+
+/// Extra columns for the procedure extension.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CallRegisters {
+    /// call-stack depth
+    pub depth: FieldElement,
+    /// return address on top of the call stack, 0 when empty
+    pub ra: FieldElement,
 }
 
 impl Default for Registers {
@@ -34,6 +45,7 @@ impl Registers {
             mp: FieldElement::zero(),
             mv: FieldElement::zero(),
             mvi: FieldElement::zero(),
+            call: None,
         }
     }
 }
@@ -44,7 +56,11 @@ impl std::fmt::Display for Registers {
             f,
             "clk:{}, ip: {}, ci: {}, ni: {}, mp: {}, mv: {}, mvi: {}",
             self.clk, self.ip, self.ci, self.ni, self.mp, self.mv, self.mvi
-        )
+        )?;
+        if let Some(call) = &self.call {
+            write!(f, ", depth: {}, ra: {}", call.depth, call.ra)?;
+        }
+        Ok(())
     }
 }
 
@@ -54,6 +70,10 @@ impl std::fmt::Debug for Registers {
             f,
             "clk:{}, ip: {}, ci: {}, ni: {}, mp: {}, mv: {}, mvi: {}",
             self.clk, self.ip, self.ci, self.ni, self.mp, self.mv, self.mvi
-        )
+        )?;
+        if let Some(call) = &self.call {
+            write!(f, ", depth: {}, ra: {}", call.depth, call.ra)?;
+        }
+        Ok(())
     }
 }